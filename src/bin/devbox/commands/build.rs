@@ -1,3 +1,4 @@
+use devbox::HookFailed;
 use prelude::*;
 use rayon::prelude::*;
 
@@ -6,36 +7,43 @@ pub fn cli() -> App {
         .about("Build infrastructure")
         .arg(Arg::with_name("SERVICE").help("The name of the service to build"))
         .arg(project())
+        .arg(profile())
 }
 
 pub fn exec(args: &ArgMatches) -> CliResult {
     let mut project = args.project()?;
+    project.write_env_file()?;
+    project.write_network_overrides()?;
 
     if let Some(name) = args.value_of("SERVICE") {
         let mut service = project.find_service(name)?;
         let _ = service.clone_repo();
         service.build()
     } else {
-        let _ = create_network(&project);
+        project.run_lifecycle_hooks("before-build")?;
+
+        create_networks(&project)?;
         let _ = create_volumes(&project);
-        let _ = pull_latest_images();
-        let _ = build_images();
-        let _ = clone_services(&mut project);
-        let _ = build_services(&mut project);
+        let _ = pull_latest_images(&project);
+        let _ = build_images(&project);
+        clone_services(&mut project)?;
+        build_services(&mut project)?;
 
-        Ok(())
+        project.run_lifecycle_hooks("after-build")
     }
 }
 
-fn create_network(project: &Project) -> CliResult {
-    println!("\nCreating '{}' network", &project.name);
+fn create_networks(project: &Project) -> CliResult {
+    println!("\nCreating networks");
+
+    for network in &project.networks {
+        if network.create()? {
+            println!("Created network: {}", network.name);
+        } else {
+            println!("Network already exists: {}", network.name);
+        }
+    }
 
-    let _ = docker()
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .args(&["network", "create", &project.name])
-        .spawn()?
-        .wait();
     Ok(())
 }
 
@@ -59,32 +67,76 @@ fn create_volume(name: &str) -> CliResult {
     Ok(())
 }
 
-fn pull_latest_images() -> CliResult {
+// Without any services named compose would act on all of them, so nothing is
+// done when the profile selects none.
+fn pull_latest_images(project: &Project) -> CliResult {
+    let services = project.compose_services()?;
+
+    if services.is_empty() {
+        return Ok(());
+    }
+
     println!("\nPulling latest images...");
-    let _ = docker_compose().args(&["pull"]).spawn()?.wait();
+    let _ = project
+        .docker_compose()?
+        .args(["pull", "--ignore-pull-failures"])
+        .args(services)
+        .spawn()?
+        .wait();
     Ok(())
 }
 
-fn build_images() -> CliResult {
+// Service images are built by `build_services`, from their own directories.
+fn build_images(project: &Project) -> CliResult {
+    let services = project.infrastructure_services()?;
+
+    if services.is_empty() {
+        return Ok(());
+    }
+
     println!("\nBuilding images...");
-    let _ = docker_compose().args(&["build"]).spawn()?.wait();
+    let _ = project
+        .docker_compose()?
+        .arg("build")
+        .args(services)
+        .spawn()?
+        .wait();
     Ok(())
 }
 
 fn clone_services(project: &mut Project) -> CliResult {
-    project
-        .services
-        .par_iter_mut()
-        .map(|ref mut service| service.clone_repo())
+    let results = project
+        .selected_services()
+        .into_par_iter()
+        .map(|service| service.clone_repo())
         .collect::<Vec<CliResult>>();
-    Ok(())
+
+    aborted_by_hook(results)
 }
 
 fn build_services(project: &mut Project) -> CliResult {
-    project
-        .services
-        .par_iter_mut()
-        .map(|ref mut service| service.build())
+    let results = project
+        .selected_services()
+        .into_par_iter()
+        .map(|service| {
+            // Services cloned earlier haven't loaded their hooks yet.
+            service.rehydrate_from_devbox_toml()?;
+            service.build()
+        })
         .collect::<Vec<CliResult>>();
-    Ok(())
+
+    aborted_by_hook(results)
+}
+
+// A service that fails to clone or build doesn't stop the others, but a hook
+// whose policy is `abort` stops the whole build.
+fn aborted_by_hook(results: Vec<CliResult>) -> CliResult {
+    match results
+        .into_iter()
+        .filter_map(|result| result.err())
+        .find(|err| err.downcast_ref::<HookFailed>().is_some())
+    {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
 }
@@ -0,0 +1,119 @@
+use colored::*;
+use devbox::docker::{self, Resource};
+use prelude::*;
+use prettytable::format;
+use prettytable::Table;
+use std::io::{self, Write};
+
+pub fn cli() -> App {
+    subcommand("clean")
+        .about("Remove the project's stopped containers, images, and volumes")
+        .arg(
+            Arg::with_name("yes")
+                .short("y")
+                .long("yes")
+                .help("Don't ask for confirmation"),
+        )
+        .arg(project())
+}
+
+pub fn exec(args: &ArgMatches) -> CliResult {
+    let project = args.project()?;
+    let resources = project_resources(&project)?;
+
+    if resources.is_empty() {
+        println!("Nothing to clean for project {}", project.name);
+        return Ok(());
+    }
+
+    print_resources(&resources);
+
+    if !confirm_removal(&resources, args.is_present("yes"))? {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    remove(&resources)
+}
+
+/// Everything belonging to the project, in the order it can be removed:
+/// containers first, since they hold on to images and volumes.
+fn project_resources(project: &Project) -> Result<Vec<Resource>> {
+    let mut resources = docker::project_containers(&project.name)?;
+    let services = project.compose_files().services()?;
+
+    resources.extend(docker::project_images(&project.name, &services)?);
+
+    for mut volume in docker::project_volumes(&project.name)? {
+        volume.size = docker::volume_size(&volume.name)?;
+        resources.push(volume);
+    }
+
+    Ok(resources)
+}
+
+fn print_resources(resources: &[Resource]) {
+    let mut table = Table::new();
+
+    table.set_format(*format::consts::FORMAT_CLEAN);
+    table.add_row(row!["TYPE", "NAME", "SIZE"]);
+
+    for resource in resources {
+        table.add_row(row![resource.kind, resource.name, size(resource.size)]);
+    }
+
+    table.printstd();
+
+    println!("\nReclaimable: {}\n", size(Some(total_size(resources))));
+}
+
+/// Removes each resource, carrying on past failures so that one image in use
+/// doesn't keep everything else around.
+fn remove(resources: &[Resource]) -> CliResult {
+    let mut removed = Vec::new();
+    let mut failed = 0;
+
+    for (index, resource) in resources.iter().enumerate() {
+        print!(
+            "[{}/{}] Removing {} {}... ",
+            index + 1,
+            resources.len(),
+            resource.kind,
+            resource.name
+        );
+        io::stdout().flush()?;
+
+        match resource.remove() {
+            Ok(()) => {
+                println!("{}", "done".green());
+                removed.push(resource.clone());
+            }
+            Err(err) => {
+                println!("{}", "failed".red());
+                eprintln!("  {}", err);
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "\nRemoved {} of {}, freeing {}",
+        removed.len(),
+        resources.len(),
+        size(Some(total_size(&removed)))
+    );
+
+    if failed > 0 {
+        return Err(format_err!("{} of {} could not be removed", failed, resources.len()));
+    }
+
+    Ok(())
+}
+
+fn total_size(resources: &[Resource]) -> u64 {
+    resources.iter().filter_map(|resource| resource.size).sum()
+}
+
+fn size(size: Option<u64>) -> String {
+    size.map(docker::format_size).unwrap_or_else(|| "-".into())
+}
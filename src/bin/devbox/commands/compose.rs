@@ -0,0 +1,105 @@
+use std::fs;
+
+use colored::*;
+use prelude::*;
+
+pub fn cli() -> App {
+    subcommand("compose")
+        .about("Inspect the compose files devbox hands to docker-compose")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            subcommand("render")
+                .about("Print the merged and interpolated compose file")
+                .arg(
+                    Arg::with_name("SERVICE")
+                        .multiple(true)
+                        .help("Only render these services and the ones they depend on"),
+                )
+                .arg(
+                    Arg::with_name("explain")
+                        .long("explain")
+                        .help("Note which files each key came from"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Write the compose file to FILE instead of printing it"),
+                ),
+        )
+        .arg(project())
+}
+
+pub fn exec(matches: &ArgMatches) -> CliResult {
+    let mut project = matches.project()?;
+
+    let subcmd = matches
+        .subcommand_name()
+        .ok_or_else(|| format_err!("No subcommand found"))?;
+    let args = matches
+        .subcommand_matches(subcmd)
+        .ok_or_else(|| format_err!("Error fetching argument for subcommand"))?;
+
+    match subcmd {
+        "render" => render(&mut project, args),
+        _ => Ok(()),
+    }
+}
+
+fn render(project: &mut Project, args: &ArgMatches) -> CliResult {
+    let names: Vec<String> = args
+        .values_of("SERVICE")
+        .map(|names| names.map(String::from).collect())
+        .unwrap_or_default();
+
+    // A service is rendered as its own commands run it: from its `.devbox`
+    // directory, with its environment. Each service has its own directory,
+    // so only one can be rendered at a time.
+    let mut services = project
+        .services
+        .iter_mut()
+        .filter(|service| names.contains(&service.name));
+
+    let mut rendered = match (services.next(), services.next()) {
+        (Some(first), Some(second)) => {
+            return Err(format_err!(
+                "{} and {} run from their own directories; render them one at a time",
+                first.name,
+                second.name
+            ))
+        }
+        (Some(service), None) => {
+            if service.devbox_toml_file().exists() {
+                service.rehydrate_from_devbox_toml()?;
+            }
+            service.compose_files().render(&service.env)?
+        }
+        (None, _) => project.compose_files().render(&project.env)?,
+    };
+
+    if !names.is_empty() {
+        rendered.retain(&names)?;
+    }
+
+    for name in &rendered.unset {
+        eprintln!(
+            "{} {} is not set; substituting an empty string",
+            "WARN".yellow(),
+            name
+        );
+    }
+
+    let yaml = rendered.to_yaml(args.is_present("explain"))?;
+
+    match args.value_of("output") {
+        Some(path) => {
+            fs::write(path, yaml)?;
+            eprintln!("Wrote {}", path);
+        }
+        None => print!("{}", yaml),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,241 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use colored::*;
+use devbox::{self, ConfigFile};
+use prelude::*;
+use toml;
+
+pub fn cli() -> App {
+    subcommand("config")
+        .about("Inspect and edit the project's configuration")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            subcommand("show")
+                .about("Print the project's config.toml and config.local.toml")
+                .arg(
+                    Arg::with_name("resolved")
+                        .long("resolved")
+                        .help("Merge in the files it extends and includes, noting where each value came from"),
+                ),
+        )
+        .subcommand(
+            subcommand("get")
+                .about("Print a value of the resolved config")
+                .arg(key()),
+        )
+        .subcommand(
+            subcommand("set")
+                .about("Set a value in config.toml, keeping its comments and formatting")
+                .arg(key())
+                .arg(
+                    Arg::with_name("VALUE")
+                        .required(true)
+                        .help("The value: a boolean, integer, array, inline table, or quoted string as in TOML, and a string otherwise"),
+                )
+                .arg(local()),
+        )
+        .subcommand(
+            subcommand("edit")
+                .about("Open config.toml in $EDITOR, rolling back changes that don't load")
+                .arg(local()),
+        )
+        .subcommand(
+            subcommand("add-service")
+                .about("Add a service to config.toml")
+                .arg(
+                    Arg::with_name("NAME")
+                        .required(true)
+                        .help("The name of the service"),
+                )
+                .arg(
+                    Arg::with_name("git")
+                        .long("git")
+                        .takes_value(true)
+                        .value_name("URL")
+                        .required_unless("path")
+                        .help("The git repository to clone the service from"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("Where the service's source lives, relative to the current directory"),
+                )
+                .arg(local()),
+        )
+        .arg(project())
+}
+
+pub fn exec(matches: &ArgMatches) -> CliResult {
+    let name = matches
+        .value_of("PROJECT")
+        .ok_or_else(|| format_err!("Project name required"))?;
+
+    let subcmd = matches
+        .subcommand_name()
+        .ok_or_else(|| format_err!("No subcommand found"))?;
+    let args = matches
+        .subcommand_matches(subcmd)
+        .ok_or_else(|| format_err!("Error fetching argument for subcommand"))?;
+
+    match subcmd {
+        "show" if args.is_present("resolved") => {
+            print!("{}", Project::load_config(name)?.to_annotated_toml());
+            Ok(())
+        }
+        "show" => {
+            let file = devbox::toml_config_path(name)?;
+            print!("{}", fs::read_to_string(&file)?);
+
+            let local = devbox::local_config_path(name)?;
+            if local.exists() {
+                println!("\n# Local overrides from {}\n", local.display());
+                print!("{}", fs::read_to_string(&local)?);
+            }
+
+            Ok(())
+        }
+        "get" => {
+            let key = args
+                .value_of("KEY")
+                .ok_or_else(|| format_err!("No `KEY` supplied"))?;
+
+            match Project::load_config(name)?.get(key) {
+                Some(toml::Value::String(value)) => println!("{}", value),
+                Some(value) => println!("{}", devbox::format_value(value)),
+                None => Err(format_err!("`{}` is not set", key))?,
+            }
+
+            Ok(())
+        }
+        "set" => {
+            let key = args
+                .value_of("KEY")
+                .ok_or_else(|| format_err!("No `KEY` supplied"))?;
+            let value = args
+                .value_of("VALUE")
+                .ok_or_else(|| format_err!("No `VALUE` supplied"))?;
+            let path = config_path(args, name)?;
+
+            let mut file = ConfigFile::open(&path)?;
+            file.set(key, value)?;
+            file.save_checked(|| check(name))?;
+
+            println!("Set {} in {}", key, path.display());
+            Ok(())
+        }
+        "add-service" => {
+            let name = args
+                .value_of("NAME")
+                .ok_or_else(|| format_err!("No `NAME` supplied"))?;
+            let path = config_path(args, name)?;
+
+            let source = match args.value_of("path") {
+                Some(source) => Some(absolute_path(source)?),
+                None => None,
+            };
+
+            let mut file = ConfigFile::open(&path)?;
+            file.add_service(name, args.value_of("git"), source.as_deref())?;
+            file.save_checked(|| check(name))?;
+
+            println!("Added service {} to {}", name, path.display());
+            Ok(())
+        }
+        "edit" => edit(&config_path(args, name)?, name),
+        _ => Ok(()),
+    }
+}
+
+fn key() -> Arg {
+    Arg::with_name("KEY")
+        .required(true)
+        .help("A dotted key, such as services.api.path")
+}
+
+fn local() -> Arg {
+    Arg::with_name("local")
+        .long("local")
+        .help("Change config.local.toml instead")
+}
+
+/// The file to change, found without loading the project so that a config
+/// that no longer loads can still be fixed.
+fn config_path(args: &ArgMatches, project_name: &str) -> Result<PathBuf> {
+    if args.is_present("local") {
+        devbox::local_config_path(project_name)
+    } else {
+        devbox::toml_config_path(project_name)
+    }
+}
+
+/// Makes a `--path` absolute, since devbox runs services from other
+/// directories. Paths starting with a variable are left to interpolation.
+fn absolute_path(path: &str) -> Result<String> {
+    if path.starts_with('$') {
+        return Ok(path.to_owned());
+    }
+
+    let path = env::current_dir()?.join(path);
+    let path = fs::canonicalize(&path).unwrap_or(path);
+
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Loads the project from scratch to make sure its config is still valid.
+fn check(project_name: &str) -> Result<()> {
+    Project::new(project_name).map(|_| ())
+}
+
+/// Opens `path` in the user's editor until the project loads again, or puts
+/// the file back the way it was.
+fn edit(path: &Path, project_name: &str) -> CliResult {
+    let original = fs::read_to_string(path).ok();
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+
+    let rollback = || -> Result<()> {
+        match original {
+            Some(ref original) => fs::write(path, original)?,
+            None if path.exists() => fs::remove_file(path)?,
+            None => {}
+        }
+        Ok(())
+    };
+
+    loop {
+        // Run through the shell so editors configured with arguments work.
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor))
+            .arg("sh")
+            .arg(path)
+            .status()?;
+
+        if !status.success() {
+            rollback()?;
+            return Err(format_err!("{} exited with {}; changes rolled back", editor, status));
+        }
+
+        match check(project_name) {
+            Ok(()) => {
+                println!("Saved {}", path.display());
+                return Ok(());
+            }
+            Err(err) => {
+                eprintln!("{}", "The config doesn't load:".red());
+                err.iter_chain()
+                    .for_each(|cause| eprintln!("{}", format!("{}", cause).red()));
+
+                if !confirm("Edit it again?")? {
+                    rollback()?;
+                    return Err(format_err!("Changes to {} were rolled back", path.display()));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use devbox::docker::{self, Resource};
+use prelude::*;
+
+pub fn cli() -> App {
+    subcommand("destroy")
+        .about("Remove the project's containers and network, and optionally its volumes and images")
+        .arg(
+            Arg::with_name("volumes")
+                .long("volumes")
+                .help("Also remove the project's volumes, including their data"),
+        )
+        .arg(
+            Arg::with_name("images")
+                .long("images")
+                .help("Also remove the images built for the project"),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .short("y")
+                .long("yes")
+                .help("Don't ask for confirmation"),
+        )
+        .arg(project())
+}
+
+pub fn exec(args: &ArgMatches) -> CliResult {
+    let mut project = args.project()?;
+
+    let volumes = if args.is_present("volumes") {
+        project_volumes(&project)?
+    } else {
+        Vec::new()
+    };
+    let images = if args.is_present("images") {
+        docker::project_images(&project.name, &project.compose_files().services()?)?
+    } else {
+        Vec::new()
+    };
+
+    print_plan(&project, &volumes, &images);
+
+    let resources: Vec<Resource> = volumes.iter().chain(&images).cloned().collect();
+
+    if !confirm_removal(&resources, args.is_present("yes"))? {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    run_destroy_hooks(&mut project)?;
+
+    let status = project
+        .docker_compose()?
+        .args(["down", "--remove-orphans"])
+        .spawn()?
+        .wait()?;
+    if !status.success() {
+        return Err(format_err!("Failed to take down project {}", project.name));
+    }
+
+    for resource in &resources {
+        resource.remove()?;
+        println!("Removed {} {}", resource.kind, resource.name);
+    }
+
+    Ok(())
+}
+
+/// The volumes `docker-compose` created for the project, along with the
+/// external ones listed in its `volumes`.
+fn project_volumes(project: &Project) -> Result<Vec<Resource>> {
+    let mut volumes = docker::project_volumes(&project.name)?;
+
+    for name in &project.volumes {
+        if !volumes.iter().any(|volume| volume.name == *name) && docker::volume_exists(name)? {
+            volumes.push(Resource::volume(name));
+        }
+    }
+
+    volumes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(volumes)
+}
+
+fn print_plan(project: &Project, volumes: &[Resource], images: &[Resource]) {
+    println!(
+        "This will remove the containers and network of project {}",
+        project.name
+    );
+
+    if !volumes.is_empty() {
+        println!("\nVolumes:");
+        for volume in volumes {
+            println!("  {}", volume.name);
+        }
+    }
+
+    if !images.is_empty() {
+        println!("\nImages:");
+        for image in images {
+            println!("  {} ({})", image.name, image.id);
+        }
+    }
+
+    println!();
+}
+
+fn run_destroy_hooks(project: &mut Project) -> CliResult {
+    project.run_lifecycle_hooks("before-destroy")?;
+
+    let names: Vec<String> = project.services.iter().map(|s| s.name.clone()).collect();
+
+    for name in names {
+        project
+            .find_service(&name)?
+            .run_lifecycle_hooks("before-destroy")?;
+    }
+
+    Ok(())
+}
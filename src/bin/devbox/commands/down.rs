@@ -0,0 +1,22 @@
+use prelude::*;
+
+pub fn cli() -> App {
+    subcommand("down")
+        .about("Stop and remove the project's containers and network, keeping volumes")
+        .arg(project())
+}
+
+pub fn exec(args: &ArgMatches) -> CliResult {
+    let project = args.project()?;
+
+    let status = project
+        .docker_compose()?
+        .args(["down", "--remove-orphans"])
+        .spawn()?
+        .wait()?;
+    if !status.success() {
+        return Err(format_err!("Failed to take down project {}", project.name));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use devbox::Import;
+use prelude::*;
+
+pub fn cli() -> App {
+    subcommand("import")
+        .about("Creates a devbox project from an existing docker-compose setup")
+        .arg(
+            Arg::with_name("PROJECT")
+                .required(true)
+                .help("The project name"),
+        )
+        .arg(
+            Arg::with_name("compose")
+                .long("compose")
+                .short("f")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+                .value_name("FILE")
+                .help("A compose file to import; repeat it for overrides, in the order compose would read them"),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .short("y")
+                .long("yes")
+                .help("Don't ask before writing files into the services' source directories"),
+        )
+}
+
+pub fn exec(args: &ArgMatches) -> CliResult {
+    let name = args
+        .value_of("PROJECT")
+        .ok_or_else(|| format_err!("Missing project name"))?;
+    let files: Vec<PathBuf> = args
+        .values_of("compose")
+        .ok_or_else(|| format_err!("No `--compose` file supplied"))?
+        .map(PathBuf::from)
+        .collect();
+
+    let import = Import::new(name, &files)?;
+    let overlays = import.new_overlays();
+
+    if !overlays.is_empty() {
+        println!("The services that build will get their own compose files:");
+        for path in &overlays {
+            println!("  {}", path.display());
+        }
+        println!();
+
+        if !args.is_present("yes") && !confirm("Write them into the source directories?")? {
+            println!("Aborted");
+            return Ok(());
+        }
+    }
+
+    for path in import.write()? {
+        println!("Created {}", path.display());
+    }
+
+    for (service, path) in &import.paths {
+        println!("Registered {} as a path service at {}", service, path.display());
+    }
+
+    for (service, dependency) in &import.dropped_dependencies {
+        println!(
+            "Dropped {}'s depends_on {}; start {} with devbox start",
+            service, dependency, dependency
+        );
+    }
+
+    Ok(())
+}
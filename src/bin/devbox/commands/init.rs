@@ -0,0 +1,70 @@
+use std::env;
+
+use devbox::{self, ConfigFile, Scaffold};
+use prelude::*;
+
+pub fn cli() -> App {
+    subcommand("init")
+        .about("Generate the .devbox files of the service in the current directory")
+        .arg(
+            Arg::with_name("name")
+                .long("name")
+                .takes_value(true)
+                .help("The name of the service (defaults to the directory name)"),
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replace existing .devbox files"),
+        )
+        .arg(
+            project()
+                .required(false)
+                .help("The project to add the service to"),
+        )
+}
+
+pub fn exec(args: &ArgMatches) -> CliResult {
+    let dir = env::current_dir()?;
+    let name = match args.value_of("name") {
+        Some(name) => name.to_owned(),
+        None => dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| format_err!("Unable to name the service; pass --name"))?,
+    };
+
+    let project = match args.value_of("PROJECT") {
+        Some(project) => Some(Project::new(project)?),
+        None => None,
+    };
+    let network = project
+        .as_ref()
+        .and_then(|project| project.networks.first())
+        .map(|network| network.name.clone());
+
+    let scaffold = Scaffold::new(&name, &dir, network)?;
+    println!("Detected a {} service", scaffold.stack);
+
+    for file in scaffold.write(args.is_present("force"))? {
+        println!("Created {}", file.display());
+    }
+
+    if let Some(project) = project {
+        if project.config.get(&format!("services.{}", name)).is_some() {
+            println!("Service {} is already part of project {}", name, project.name);
+            return Ok(());
+        }
+
+        let git = devbox::git_origin(&dir);
+        let path = dir.to_string_lossy();
+
+        let mut file = ConfigFile::open(project.config_file()?)?;
+        file.add_service(&name, git.as_deref(), Some(&path))?;
+        file.save_checked(|| Project::new(&project.name).map(|_| ()))?;
+
+        println!("Added service {} to project {}", name, project.name);
+    }
+
+    Ok(())
+}
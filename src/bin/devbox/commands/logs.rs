@@ -1,4 +1,6 @@
+use devbox::{self, LogArchive, LogOptions, LogSource};
 use prelude::*;
+use regex::Regex;
 
 pub fn cli() -> App {
     subcommand("logs")
@@ -18,6 +20,54 @@ pub fn cli() -> App {
                 .value_name("integer or \"all\"")
                 .help("Number of lines to show from the end of the logs for each container."),
         )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .value_name("timestamp or duration")
+                .help("Show logs since a timestamp (e.g. 2018-01-02T13:23:37) or relative duration (e.g. 42m)"),
+        )
+        .arg(
+            Arg::with_name("until")
+                .long("until")
+                .takes_value(true)
+                .value_name("timestamp or duration")
+                .help("Show logs before a timestamp (e.g. 2018-01-02T13:23:37) or relative duration (e.g. 42m)"),
+        )
+        .arg(
+            Arg::with_name("grep")
+                .long("grep")
+                .takes_value(true)
+                .value_name("regex")
+                .help("Only show lines matching a regular expression"),
+        )
+        .arg(
+            Arg::with_name("level")
+                .long("level")
+                .takes_value(true)
+                .value_name("level")
+                .help("Only show JSON log lines at this level or above (trace, debug, info, warn, error, fatal)"),
+        )
+        .arg(
+            Arg::with_name("timestamps")
+                .long("timestamps")
+                .help("Show timestamps"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .requires("follow")
+                .conflicts_with("since")
+                .help("Also save the followed logs under ~/.config/devbox/<project>/logs"),
+        )
+        .arg(
+            Arg::with_name("history")
+                .long("history")
+                .takes_value(true)
+                .value_name("SERVICE")
+                .conflicts_with_all(&["SERVICE", "follow", "record"])
+                .help("Show the recorded logs of a service, even after its containers are gone"),
+        )
         .arg(
             Arg::with_name("SERVICE")
                 .multiple(true)
@@ -25,55 +75,54 @@ pub fn cli() -> App {
                 .help("The name of the service(s) to log"),
         )
         .arg(project())
+        .arg(profile())
 }
 
 pub fn exec(args: &ArgMatches) -> CliResult {
-    let mut project = args.project()?;
-
-    let compose_file = &project.docker_compose_file.clone();
-
-    let mut cmd = Command::new("docker-compose");
+    let mut options = log_options(args)?;
+    let project = args.project()?;
+    let archive = LogArchive::new(&project.name, &project.logs)?;
 
-    // Set the devbox compose file
-    cmd.arg("-f").arg(compose_file.clone());
-
-    if let Some(names) = args.values_of("SERVICE") {
-        for name in names {
-            maybe_append_docker_compose_override(&mut cmd, name, &mut project);
-        }
+    if let Some(service) = args.value_of("history") {
+        return archive.print_history(service, &options);
     }
 
-    cmd.arg("logs");
-
-    if args.is_present("follow") {
-        cmd.arg("-f");
+    // Only followed logs are recorded, otherwise every run would append the
+    // history already recorded. A recorded stream starts where the last
+    // recording stopped, so one starting at `--since` isn't recorded either.
+    let record = args.is_present("record") || project.logs.record;
+    if options.follow && options.since.is_none() && record {
+        options.record = Some(archive);
     }
 
-    if let Some(tail) = args.value_of("tail") {
-        cmd.arg("--tail").arg(tail);
-    }
+    let services = match args.values_of_lossy("SERVICE") {
+        Some(names) => names,
+        None => project.compose_services()?,
+    };
 
-    if let Some(names) = args.values_of("SERVICE") {
-        names.for_each(|name| {
-            cmd.arg(name);
-        });
-    }
+    let sources = LogSource::discover(|| project.docker_compose(), &services)?;
+    devbox::stream(&sources, &options)
+}
 
-    let _ = cmd.spawn()?.wait();
+fn log_options(args: &ArgMatches) -> Result<LogOptions> {
+    let grep = match args.value_of("grep") {
+        Some(pattern) => Some(Regex::new(pattern).context("Invalid `--grep` pattern")?),
+        None => None,
+    };
 
-    Ok(())
-}
+    let level = match args.value_of("level") {
+        Some(level) => Some(level.parse()?),
+        None => None,
+    };
 
-fn maybe_append_docker_compose_override<'a>(
-    cmd: &'a mut Command,
-    name: &str,
-    project: &'a mut Project,
-) -> &'a mut Command {
-    match project.find_service(name) {
-        Ok(ref mut service) => match service.devbox_compose_file().to_str() {
-            Some(override_file) => cmd.args(&["-f", override_file]),
-            None => cmd,
-        },
-        Err(_) => cmd,
-    }
+    Ok(LogOptions {
+        follow: args.is_present("follow"),
+        tail: args.value_of("tail").map(String::from),
+        since: args.value_of("since").map(String::from),
+        until: args.value_of("until").map(String::from),
+        timestamps: args.is_present("timestamps"),
+        grep,
+        level,
+        record: None,
+    })
 }
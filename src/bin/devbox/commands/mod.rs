@@ -3,42 +3,75 @@ use prelude::*;
 pub fn builtins() -> Vec<App> {
     vec![
         build::cli(),
+        clean::cli(),
         completions::cli(),
+        compose::cli(),
+        config::cli(),
+        destroy::cli(),
         doctor::cli(),
+        down::cli(),
+        import::cli(),
+        init::cli(),
         logs::cli(),
+        network::cli(),
         new::cli(),
         ps::cli(),
+        secrets::cli(),
         start::cli(),
         stop::cli(),
         tasks::cli(),
+        template::cli(),
         update::cli(),
+        volume::cli(),
     ]
 }
 
 pub fn builtin_exec(cmd: &str) -> Option<fn(&ArgMatches) -> CliResult> {
     let f = match cmd {
         "build" => build::exec,
+        "clean" => clean::exec,
         "completions" => completions::exec,
+        "compose" => compose::exec,
+        "config" => config::exec,
+        "destroy" => destroy::exec,
         "doctor" => doctor::exec,
+        "down" => down::exec,
+        "import" => import::exec,
+        "init" => init::exec,
         "logs" => logs::exec,
+        "network" => network::exec,
         "new" => new::exec,
         "ps" => ps::exec,
+        "secrets" => secrets::exec,
         "start" => start::exec,
         "stop" => stop::exec,
         "tasks" => tasks::exec,
+        "template" => template::exec,
         "update" => update::exec,
+        "volume" => volume::exec,
         _ => return None,
     };
     Some(f)
 }
 
 pub mod build;
+pub mod clean;
 pub mod completions;
+pub mod compose;
+pub mod config;
+pub mod destroy;
 pub mod doctor;
+pub mod down;
+pub mod import;
+pub mod init;
 pub mod logs;
+pub mod network;
 pub mod new;
 pub mod ps;
+pub mod secrets;
 pub mod start;
 pub mod stop;
 pub mod tasks;
+pub mod template;
 pub mod update;
+pub mod volume;
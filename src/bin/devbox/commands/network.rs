@@ -0,0 +1,53 @@
+use devbox::{self, Network};
+use prelude::*;
+
+pub fn cli() -> App {
+    subcommand("network")
+        .about("Inspect and remove project networks")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(subcommand("ls").about("List the project's networks"))
+        .subcommand(
+            subcommand("rm")
+                .about("Remove project networks")
+                .arg(
+                    Arg::with_name("NETWORK")
+                        .multiple(true)
+                        .help("The name(s) of the network(s); defaults to all of them"),
+                ),
+        )
+        .arg(project())
+}
+
+pub fn exec(matches: &ArgMatches) -> CliResult {
+    let project = matches.project()?;
+
+    let subcmd = matches
+        .subcommand_name()
+        .ok_or_else(|| format_err!("No subcommand found"))?;
+    let args = matches
+        .subcommand_matches(subcmd)
+        .ok_or_else(|| format_err!("Error fetching argument for subcommand"))?;
+
+    match subcmd {
+        "ls" => devbox::print_networks(&project.networks),
+        "rm" => {
+            let networks: Vec<&Network> = match args.values_of("NETWORK") {
+                Some(names) => names
+                    .map(|name| project.find_network(name))
+                    .collect::<Result<_>>()?,
+                None => project.networks.iter().collect(),
+            };
+
+            for network in networks {
+                if network.remove()? {
+                    println!("Removed network {}", network.name);
+                } else {
+                    println!("Network {} does not exist", network.name);
+                }
+            }
+
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
@@ -14,6 +14,13 @@ pub fn cli() -> App {
                 .takes_value(true)
                 .help("A URL to a git repository containing configuration for this project"),
         )
+        .arg(
+            Arg::with_name("template")
+                .long("template")
+                .takes_value(true)
+                .conflicts_with("git")
+                .help("The template to create the project from; see `devbox template list`"),
+        )
 }
 
 pub fn exec(args: &ArgMatches) -> CliResult {
@@ -21,6 +28,6 @@ pub fn exec(args: &ArgMatches) -> CliResult {
         .ok_or_else(|| format_err!("Missing project name"))?;
     match args.value_of("git") {
         Some(repo) => Project::init_from_git(name, repo),
-        None => Project::init(name),
+        None => Project::init(name, args.value_of("template")),
     }
 }
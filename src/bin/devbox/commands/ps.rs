@@ -1,17 +1,31 @@
 use prelude::*;
 
 pub fn cli() -> App {
-    subcommand("ps").about("Display running services")
+    subcommand("ps")
+        .about("Display running services")
+        .arg(project())
 }
 
-pub fn exec(_args: &ArgMatches) -> CliResult {
-    let _ = docker()
-        .args(&[
-            "ps",
-            "--format",
-            "table {{.ID}}\t{{.Names}}\t{{.Status}}\t{{.Ports}}",
-        ])
-        .spawn()?
-        .wait();
+pub fn exec(args: &ArgMatches) -> CliResult {
+    let project = args.project()?;
+    let containers = project.containers()?;
+
+    if containers.is_empty() {
+        println!("No running containers in project {}", project.name);
+        return Ok(());
+    }
+
+    let mut cmd = docker();
+    cmd.args([
+        "ps",
+        "--format",
+        "table {{.ID}}\t{{.Names}}\t{{.Status}}\t{{.Ports}}",
+    ]);
+
+    for id in &containers {
+        cmd.arg("--filter").arg(format!("id={}", id));
+    }
+
+    let _ = cmd.spawn()?.wait();
     Ok(())
 }
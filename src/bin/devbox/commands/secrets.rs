@@ -0,0 +1,79 @@
+use devbox::{self, SecretProvider};
+use prelude::*;
+
+pub fn cli() -> App {
+    subcommand("secrets")
+        .about("Manage the project's local encrypted secrets")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            subcommand("set")
+                .about("Store a secret, read from the terminal unless VALUE is given")
+                .arg(key())
+                .arg(Arg::with_name("VALUE").help("The value of the secret")),
+        )
+        .subcommand(
+            subcommand("get")
+                .about("Print a local secret, or any secret given as secret://<provider>/<key>")
+                .arg(key()),
+        )
+        .subcommand(subcommand("list").about("List the names of the local secrets"))
+        .arg(project())
+}
+
+pub fn exec(matches: &ArgMatches) -> CliResult {
+    let project = matches.project()?;
+    let local = project.secrets.local();
+
+    let subcmd = matches
+        .subcommand_name()
+        .ok_or_else(|| format_err!("No subcommand found"))?;
+    let args = matches
+        .subcommand_matches(subcmd)
+        .ok_or_else(|| format_err!("Error fetching argument for subcommand"))?;
+
+    match subcmd {
+        "set" => {
+            let key = args
+                .value_of("KEY")
+                .ok_or_else(|| format_err!("No `KEY` supplied"))?;
+            let value = match args.value_of("VALUE") {
+                Some(value) => value.to_owned(),
+                None => devbox::read_hidden(&format!("Value of {}: ", key))?,
+            };
+
+            local.set(key, &value)?;
+            println!("Stored secret {}; use it as {}local/{}", key, devbox::SECRET_SCHEME, key);
+            Ok(())
+        }
+        "get" => {
+            let key = args
+                .value_of("KEY")
+                .ok_or_else(|| format_err!("No `KEY` supplied"))?;
+            let value = match key.strip_prefix(devbox::SECRET_SCHEME) {
+                Some(reference) => {
+                    let (provider, key) = reference
+                        .split_once('/')
+                        .ok_or_else(|| format_err!("Expected secret://<provider>/<key>"))?;
+                    project.secrets.provider(provider)?.get(key)?
+                }
+                None => local.get(key)?,
+            };
+
+            println!("{}", value);
+            Ok(())
+        }
+        "list" => {
+            for key in local.list()? {
+                println!("{}", key);
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn key() -> Arg {
+    Arg::with_name("KEY")
+        .required(true)
+        .help("The name of the secret")
+}
@@ -4,20 +4,63 @@ pub fn cli() -> App {
     subcommand("start")
         .about("Start infrastructure or service")
         .arg(Arg::with_name("SERVICE").help("The name of the service to start"))
+        .arg(
+            Arg::with_name("remap")
+                .long("remap")
+                .help("Move services whose published ports are taken to free ports"),
+        )
         .arg(project())
+        .arg(profile())
 }
 
 pub fn exec(args: &ArgMatches) -> CliResult {
     let mut project = args.project()?;
+    let remap = args.is_present("remap");
+    project.write_env_file()?;
+    project.write_network_overrides()?;
 
     match args.value_of("SERVICE") {
         Some(name) => {
             let service = project.find_service(name)?;
+            service.check_ports(remap)?;
             service.start()
         }
         None => {
-            let _ = docker_compose().args(&["up", "-d"]).spawn()?.wait();
-            Ok(())
+            project.check_ports(remap)?;
+            project.run_lifecycle_hooks("before-start")?;
+
+            // Overlays are included so they can adjust the project's own
+            // services, but only those are started: the services themselves
+            // are started one at a time, from their own directories. With
+            // none selected `up` would start everything, so it's skipped.
+            let infrastructure = project.infrastructure_services()?;
+
+            if !infrastructure.is_empty() {
+                let status = project
+                    .docker_compose()?
+                    .envs(project.resolved_env()?)
+                    .args(["up", "-d"])
+                    .args(&infrastructure)
+                    .spawn()?
+                    .wait()?;
+                if !status.success() {
+                    return Err(format_err!("Failed to start project {}", project.name));
+                }
+
+                project.wait_until_healthy(&infrastructure)?;
+            }
+
+            // A profile can name services too, which are started once the
+            // infrastructure they depend on is up.
+            if project.profile.is_some() {
+                for service in project.selected_services() {
+                    service.rehydrate_from_devbox_toml()?;
+                    service.check_ports(remap)?;
+                    service.start()?;
+                }
+            }
+
+            project.run_lifecycle_hooks("after-start")
         }
     }
 }
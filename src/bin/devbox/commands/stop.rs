@@ -1,5 +1,4 @@
 use prelude::*;
-use std::io::prelude::*;
 
 pub fn cli() -> App {
     subcommand("stop")
@@ -17,36 +16,21 @@ pub fn exec(args: &ArgMatches) -> CliResult {
             service.stop()
         }
         None => {
-            let _ = destroy_environment();
-            let _ = remove_images();
-            Ok(())
-        }
-    }
-}
-
-fn destroy_environment() -> CliResult {
-    let _ = docker_compose()
-        .args(&["down", "-v", "--remove-orphans"])
-        .spawn()?
-        .wait();
-    Ok(())
-}
-
-fn remove_images() -> CliResult {
-    let images = docker()
-        .args(&["images", "-q", "-f", "dangling=true"])
-        .output()?;
+            project.run_lifecycle_hooks("before-stop")?;
 
-    let command = Command::new("xargs")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .args(&["-I", "ARGS", "docker", "rmi", "-f", "ARGS"])
-        .spawn()?;
+            // `stop` below stops the services too, so their own hooks run
+            // first, as they would when stopping each one.
+            for service in project.selected_services() {
+                service.rehydrate_from_devbox_toml()?;
+                service.run_lifecycle_hooks("before-stop")?;
+            }
 
-    command
-        .stdin
-        .ok_or_else(|| format_err!("Failed to read stdin"))?
-        .write_all(&images.stdout)?;
+            let status = project.docker_compose()?.arg("stop").spawn()?.wait()?;
+            if !status.success() {
+                return Err(format_err!("Failed to stop project {}", project.name));
+            }
 
-    Ok(())
+            Ok(())
+        }
+    }
 }
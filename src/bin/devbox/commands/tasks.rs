@@ -1,4 +1,7 @@
+use devbox::{self, Prefix, Service, Task, TaskMode, TaskResult};
 use prelude::*;
+use rayon::prelude::*;
+use std::io::{self, Write};
 
 pub fn cli() -> App {
     subcommand("tasks")
@@ -12,8 +15,8 @@ pub fn cli() -> App {
             ),
         )
         .subcommand(
-            subcommand("exec")
-                .about("Execute tasks for an service")
+            subcommand("run")
+                .about("Pick a task to run from a menu, or run the named tasks")
                 .arg(
                     Arg::with_name("SERVICE")
                         .required(true)
@@ -21,9 +24,51 @@ pub fn cli() -> App {
                 )
                 .arg(
                     Arg::with_name("TASKS")
-                        .required(true)
+                        .multiple(true)
+                        .help("The name(s) of the task(s); omit to choose from a menu"),
+                )
+                .arg(
+                    Arg::with_name("keep-going")
+                        .short("k")
+                        .long("keep-going")
+                        .help("Continue running the remaining tasks after a task fails"),
+                ),
+        )
+        .subcommand(
+            subcommand("exec")
+                .about("Execute tasks for an service")
+                .arg(
+                    Arg::with_name("SERVICE")
+                        .required_unless_one(&["service", "all-services"])
+                        .help("The name of the service (omitted with --service or --all-services)"),
+                )
+                .arg(
+                    Arg::with_name("TASKS")
                         .multiple(true)
                         .help("The name(s) of the task(s)"),
+                )
+                .arg(
+                    Arg::with_name("service")
+                        .short("s")
+                        .long("service")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("SERVICE")
+                        .help("Run the tasks on this service; may be given more than once"),
+                )
+                .arg(
+                    Arg::with_name("all-services")
+                        .short("a")
+                        .long("all-services")
+                        .conflicts_with("service")
+                        .help("Run the tasks on every service in the project"),
+                )
+                .arg(
+                    Arg::with_name("keep-going")
+                        .short("k")
+                        .long("keep-going")
+                        .help("Continue running the remaining tasks after a task fails"),
                 ),
         )
         .arg(project())
@@ -38,17 +83,161 @@ pub fn exec(matches: &ArgMatches) -> CliResult {
     let args = matches
         .subcommand_matches(subcmd)
         .ok_or_else(|| format_err!("Error fetching argument for subcommand"))?;
-    let service_name = args.value_of("SERVICE")
-        .ok_or_else(|| format_err!("No `SERVICE` supplied"))?;
-    let service = project.find_service(service_name)?;
 
     match subcmd {
-        "list" => service.list_tasks(),
-        "exec" => {
-            let tasks = args.values_of_lossy("TASKS")
-                .ok_or_else(|| format_err!("Error parsing `TASKS`"))?;
-            service.exec_tasks(tasks)
+        "list" => {
+            let service = project.find_service(service_name(args)?)?;
+            service.list_tasks()
         }
+        "run" => {
+            project.write_env_file()?;
+            let service = project.find_service(service_name(args)?)?;
+            let tasks = match args.values_of_lossy("TASKS") {
+                Some(tasks) => tasks,
+                None => vec![pick_task(service)?],
+            };
+
+            service.exec_tasks(tasks, args.is_present("keep-going"))
+        }
+        "exec" => exec_tasks(&mut project, args),
         _ => Ok(()),
     }
 }
+
+fn exec_tasks(project: &mut Project, args: &ArgMatches) -> CliResult {
+    project.write_env_file()?;
+    let keep_going = args.is_present("keep-going");
+    let mut tasks = args.values_of_lossy("TASKS").unwrap_or_default();
+
+    if !args.is_present("service") && !args.is_present("all-services") {
+        let service = project.find_service(service_name(args)?)?;
+        return service.exec_tasks(require_tasks(tasks)?, keep_going);
+    }
+
+    // Without a positional service every positional argument is a task name.
+    if let Some(task) = args.value_of("SERVICE") {
+        tasks.insert(0, task.to_owned());
+    }
+
+    let service_names = if args.is_present("all-services") {
+        project.services.iter().map(|s| s.name.clone()).collect()
+    } else {
+        args.values_of_lossy("service").unwrap_or_default()
+    };
+
+    let all_services = args.is_present("all-services");
+    exec_parallel(project, &service_names, &require_tasks(tasks)?, keep_going, all_services)
+}
+
+/// Runs the tasks in every service at once. With `skip_missing`, services
+/// that lack one of the tasks, or the compose file to run it with, are
+/// skipped and reported rather than failing the whole run.
+fn exec_parallel(
+    project: &mut Project,
+    service_names: &[String],
+    task_names: &[String],
+    keep_going: bool,
+    skip_missing: bool,
+) -> CliResult {
+    let mut jobs: Vec<(Service, Vec<Task>)> = Vec::new();
+    let mut skipped = Vec::new();
+
+    for name in service_names {
+        let service = project.find_service(name)?;
+
+        if !skip_missing {
+            let tasks = service.resolve_tasks(task_names)?;
+            jobs.push((service.clone(), tasks));
+            continue;
+        }
+
+        let missing = task_names.iter().find(|task| service.find_task(task).is_none());
+        let tasks = match missing {
+            Some(task) => {
+                skipped.push(TaskResult::not_run(name, task, "no such task"));
+                continue;
+            }
+            None => service.resolve_tasks(task_names)?,
+        };
+
+        let needs_compose = tasks
+            .iter()
+            .any(|task| task.mode.unwrap_or(TaskMode::Exec) != TaskMode::Host);
+
+        if needs_compose && !service.devbox_compose_file().exists() {
+            for task in &tasks {
+                skipped.push(TaskResult::not_run(name, &task.name, "no docker-compose file"));
+            }
+            continue;
+        }
+
+        jobs.push((service.clone(), tasks));
+    }
+
+    let width = service_names.iter().map(|name| name.len()).max().unwrap_or(0);
+
+    let outcomes = jobs.par_iter()
+        .map(|(service, tasks)| {
+            let prefix = Prefix::new(&service.name, width);
+            service.run_tasks(tasks, keep_going, Some(&prefix))
+        })
+        .collect::<Vec<Result<Vec<TaskResult>>>>();
+
+    let mut results = skipped;
+    let mut errors = Vec::new();
+
+    for outcome in outcomes {
+        match outcome {
+            Ok(service_results) => results.extend(service_results),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    devbox::print_summary(&results);
+
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => devbox::check_results(&results),
+    }
+}
+
+/// Shows the service's tasks as a numbered menu and reads the choice, given
+/// either as a number or as a task name.
+fn pick_task(service: &mut Service) -> Result<String> {
+    let tasks = service.tasks()?;
+
+    if tasks.is_empty() {
+        return Err(format_err!("No tasks are defined for service {}", service.name));
+    }
+
+    service.list_tasks_numbered()?;
+
+    print!("\nRun which task? [1-{}]: ", tasks.len());
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+
+    if let Some(task) = tasks.iter().find(|task| task.name == answer) {
+        return Ok(task.name.clone());
+    }
+
+    match answer.parse::<usize>() {
+        Ok(index) if index >= 1 && index <= tasks.len() => Ok(tasks[index - 1].name.clone()),
+        _ => Err(service.task_not_found(answer).into()),
+    }
+}
+
+fn service_name<'a>(args: &'a ArgMatches) -> Result<&'a str> {
+    args.value_of("SERVICE")
+        .ok_or_else(|| format_err!("No `SERVICE` supplied"))
+}
+
+fn require_tasks(tasks: Vec<String>) -> Result<Vec<String>> {
+    if tasks.is_empty() {
+        Err(format_err!("No `TASKS` supplied"))
+    } else {
+        Ok(tasks)
+    }
+}
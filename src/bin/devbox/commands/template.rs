@@ -0,0 +1,74 @@
+use devbox::Templates;
+use prelude::*;
+
+pub fn cli() -> App {
+    subcommand("template")
+        .about("Manage the templates `devbox new` creates projects from")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            subcommand("list")
+                .alias("ls")
+                .about("List the built-in and installed templates"),
+        )
+        .subcommand(
+            subcommand("add")
+                .about("Install a template from a directory or a git repository")
+                .arg(
+                    Arg::with_name("NAME")
+                        .required(true)
+                        .help("The name of the template"),
+                )
+                .arg(
+                    Arg::with_name("SOURCE")
+                        .required(true)
+                        .help("A directory or git URL with a config.toml and a docker-compose.yml"),
+                ),
+        )
+        .subcommand(
+            subcommand("remove")
+                .alias("rm")
+                .about("Remove an installed template")
+                .arg(
+                    Arg::with_name("NAME")
+                        .required(true)
+                        .help("The name of the template"),
+                ),
+        )
+}
+
+pub fn exec(matches: &ArgMatches) -> CliResult {
+    let templates = Templates::new()?;
+
+    let subcmd = matches
+        .subcommand_name()
+        .ok_or_else(|| format_err!("No subcommand found"))?;
+    let args = matches
+        .subcommand_matches(subcmd)
+        .ok_or_else(|| format_err!("Error fetching argument for subcommand"))?;
+
+    match subcmd {
+        "list" => templates.print_list(),
+        "add" => {
+            let name = args
+                .value_of("NAME")
+                .ok_or_else(|| format_err!("No `NAME` supplied"))?;
+            let source = args
+                .value_of("SOURCE")
+                .ok_or_else(|| format_err!("No `SOURCE` supplied"))?;
+
+            let path = templates.add(name, source)?;
+            println!("Added template {} at {}", name, path.display());
+            Ok(())
+        }
+        "remove" => {
+            let name = args
+                .value_of("NAME")
+                .ok_or_else(|| format_err!("No `NAME` supplied"))?;
+
+            templates.remove(name)?;
+            println!("Removed template {}", name);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
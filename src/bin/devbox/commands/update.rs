@@ -2,19 +2,25 @@ use prelude::*;
 
 pub fn cli() -> App {
     subcommand("update")
-        .about("Update a service")
-        .arg(
-            Arg::with_name("SERVICE")
-                .required(true)
-                .help("The name of the service to update"),
-        )
+        .about("Update a service, or every service of the project or profile")
+        .arg(Arg::with_name("SERVICE").help("The name of the service to update"))
         .arg(project())
+        .arg(profile())
 }
 
 pub fn exec(args: &ArgMatches) -> CliResult {
     let mut project = args.project()?;
-    let name = args.value_of("SERVICE")
-        .ok_or_else(|| format_err!("Error parsing `SERVICE`"))?;
-    let service = project.find_service(name)?;
-    service.update()
+    project.write_env_file()?;
+
+    match args.value_of("SERVICE") {
+        Some(name) => project.find_service(name)?.update(),
+        None => {
+            for service in project.selected_services() {
+                service.rehydrate_from_devbox_toml()?;
+                service.update()?;
+            }
+
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,101 @@
+use devbox::Volumes;
+use prelude::*;
+
+pub fn cli() -> App {
+    subcommand("volume")
+        .about("Snapshot, restore, and clone project volumes")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            subcommand("list")
+                .about("List volumes with their size and last snapshot, or a volume's snapshots")
+                .arg(Arg::with_name("VOLUME").help("The name of the volume")),
+        )
+        .subcommand(
+            subcommand("snapshot")
+                .about("Save the contents of a volume")
+                .arg(volume())
+                .arg(tag().help("Name the snapshot (defaults to the current time)")),
+        )
+        .subcommand(
+            subcommand("restore")
+                .about("Replace the contents of a volume with a snapshot")
+                .arg(volume())
+                .arg(tag().help("The snapshot to restore (defaults to the latest)")),
+        )
+        .subcommand(
+            subcommand("clone")
+                .about("Copy a volume into a new volume")
+                .arg(volume())
+                .arg(
+                    Arg::with_name("TARGET")
+                        .required(true)
+                        .help("The name of the new volume"),
+                ),
+        )
+        .arg(project())
+}
+
+pub fn exec(matches: &ArgMatches) -> CliResult {
+    let project = matches.project()?;
+    let volumes = Volumes::new(&project)?;
+
+    let subcmd = matches
+        .subcommand_name()
+        .ok_or_else(|| format_err!("No subcommand found"))?;
+    let args = matches
+        .subcommand_matches(subcmd)
+        .ok_or_else(|| format_err!("Error fetching argument for subcommand"))?;
+
+    match subcmd {
+        "list" => match args.value_of("VOLUME") {
+            Some(volume) => volumes.print_snapshots(volume),
+            None => volumes.print_list(),
+        },
+        "snapshot" => {
+            let volume = volume_name(args)?;
+            let snapshot = volumes.snapshot(volume, args.value_of("tag"))?;
+            println!(
+                "Saved snapshot '{}' of {} to {}",
+                snapshot.tag,
+                volume,
+                snapshot.path.display()
+            );
+            Ok(())
+        }
+        "restore" => {
+            let volume = volume_name(args)?;
+            let snapshot = volumes.restore(volume, args.value_of("tag"))?;
+            println!("Restored {} from snapshot '{}'", volume, snapshot.tag);
+            Ok(())
+        }
+        "clone" => {
+            let volume = volume_name(args)?;
+            let target = args
+                .value_of("TARGET")
+                .ok_or_else(|| format_err!("No `TARGET` supplied"))?;
+            volumes.clone_volume(volume, target)?;
+            println!("Cloned {} into {}", volume, target);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn volume() -> Arg {
+    Arg::with_name("VOLUME")
+        .required(true)
+        .help("The name of the volume")
+}
+
+fn tag() -> Arg {
+    Arg::with_name("tag")
+        .short("t")
+        .long("tag")
+        .takes_value(true)
+        .value_name("TAG")
+}
+
+fn volume_name<'a>(args: &'a ArgMatches) -> Result<&'a str> {
+    args.value_of("VOLUME")
+        .ok_or_else(|| format_err!("No `VOLUME` supplied"))
+}
@@ -4,7 +4,11 @@ extern crate colored;
 extern crate devbox;
 #[macro_use]
 extern crate failure;
+#[macro_use]
+extern crate prettytable;
 extern crate rayon;
+extern crate regex;
+extern crate toml;
 
 use devbox::*;
 
@@ -24,6 +28,9 @@ fn main() {
         err.causes()
             .for_each(|cause| eprintln!("{}", format!("{}", cause).red()));
 
-        std::process::exit(1);
+        match err.downcast_ref::<TaskFailed>() {
+            Some(failed) => std::process::exit(failed.code),
+            None => std::process::exit(1),
+        }
     }
 }
@@ -1,7 +1,10 @@
 use clap::{self, SubCommand};
+use colored::*;
+use devbox::docker::{Kind, Resource};
 pub use clap::{AppSettings, ArgMatches};
 pub use devbox::{Project, Result};
 pub use failure::ResultExt;
+use std::io::{self, Write};
 pub use std::process::{Command, Stdio};
 
 pub type App = clap::App<'static, 'static>;
@@ -12,7 +15,13 @@ pub trait ArgMatchesExt {
     fn project(&self) -> Result<Project> {
         let name = self._value_of("PROJECT")
             .ok_or_else(|| format_err!("Project name required"))?;
-        Project::new(name)
+        let mut project = Project::new(name)?;
+
+        if let Some(profile) = self._value_of("PROFILE") {
+            project.select_profile(profile)?;
+        }
+
+        Ok(project)
     }
 
     fn _value_of(&self, name: &str) -> Option<&str>;
@@ -33,6 +42,15 @@ pub fn project() -> Arg {
         .required(true)
 }
 
+/// Limits a command to the services of a profile.
+pub fn profile() -> Arg {
+    Arg::with_name("PROFILE")
+        .help("Only operate on the services of this profile")
+        .long("profile")
+        .env("DEVBOX_PROFILE")
+        .takes_value(true)
+}
+
 pub fn subcommand(name: &'static str) -> App {
     SubCommand::with_name(name).settings(&[
         AppSettings::UnifiedHelpMessage,
@@ -45,6 +63,36 @@ pub fn docker() -> Command {
     Command::new("docker")
 }
 
-pub fn docker_compose() -> Command {
-    Command::new("docker-compose")
+/// Asks a yes or no question on the terminal, defaulting to no.
+pub fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N]: ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(["y", "yes"].contains(&answer.trim().to_lowercase().as_str()))
+}
+
+/// Asks before removing docker resources, warning first when any of them are
+/// volumes, since their data can't be recovered. `yes` skips the question.
+pub fn confirm_removal(resources: &[Resource], yes: bool) -> Result<bool> {
+    let volumes = resources
+        .iter()
+        .filter(|resource| resource.kind == Kind::Volume)
+        .count();
+
+    if volumes > 0 {
+        println!(
+            "{} {} volume(s) will be deleted along with the data in them",
+            "WARN".yellow(),
+            volumes
+        );
+    }
+
+    if yes {
+        return Ok(true);
+    }
+
+    confirm("Continue?")
 }
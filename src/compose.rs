@@ -0,0 +1,1731 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use colored::*;
+use failure::ResultExt;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use serde_json;
+use serde_yaml::{self, Mapping, Value};
+
+use config::normalize;
+use environment;
+use errors::*;
+use ports;
+use secrets::SECRET_SCHEME;
+use service::Service;
+
+/// Where a project's copies of its service overlays are kept.
+const OVERLAYS_DIR: &str = "overlays";
+
+/// The set of compose files devbox hands to `docker-compose`.
+///
+/// A project's set starts with the files listed in its `compose_files`, the
+/// project's `docker-compose.yml`, and the overrides devbox generates for it,
+/// followed by the `.devbox/docker-compose.yml` overlay of every service that
+/// has one, in service name order. Relative paths in every file resolve against the
+/// project directory, which is the first file's directory unless
+/// `project_directory` says otherwise, so the overlays are stacked as copies
+/// with their paths made absolute.
+///
+/// Building a set has no side effects: the copies are only written by
+/// `command`, right before compose runs.
+#[derive(Clone, Debug)]
+pub struct ComposeFiles {
+    entries: Vec<Entry>,
+    project_directory: Option<PathBuf>,
+}
+
+/// A file in a set, and what compose is given for it.
+#[derive(Clone, Debug)]
+enum Entry {
+    /// A file given to compose as it is.
+    File(PathBuf),
+    /// A service overlay whose relative paths resolve against its own
+    /// directory, given to compose as a copy at `copy` with them made
+    /// absolute.
+    Overlay { source: PathBuf, copy: PathBuf },
+    /// The port overrides `--remap` generated, of which compose is only given
+    /// the services the set defines, through a copy at `copy` when that isn't
+    /// all of them.
+    Ports { source: PathBuf, copy: PathBuf },
+}
+
+impl Entry {
+    fn path(&self) -> &Path {
+        match *self {
+            Entry::File(ref path)
+            | Entry::Overlay { source: ref path, .. }
+            | Entry::Ports { source: ref path, .. } => path,
+        }
+    }
+
+    /// Makes a parsed file what compose is given for the entry, to be merged
+    /// over `merged`, the files before it.
+    fn prepare(&self, mut file: ComposeFile, merged: &Compose) -> ComposeFile {
+        match *self {
+            Entry::File(_) => {}
+            Entry::Overlay { ref source, .. } => {
+                if let Some(dir) = source.parent() {
+                    for service in file.compose.services.values_mut() {
+                        service.resolve_paths(dir);
+                    }
+                }
+            }
+            Entry::Ports { .. } => file
+                .compose
+                .services
+                .retain(|service, _| merged.services.contains_key(service)),
+        }
+
+        file
+    }
+}
+
+/// Writes a copy of the overlay at `source` to `copy` with its relative paths
+/// made absolute, only touching the copy when the overlay changed.
+fn write_overlay_copy(source: &Path, copy: &Path) -> Result<()> {
+    let mut overlay = ComposeFile::read(source)?.compose;
+
+    if let Some(dir) = source.parent() {
+        for service in overlay.services.values_mut() {
+            service.resolve_paths(dir);
+        }
+    }
+
+    let yaml = format!("# Generated by devbox from {}\n{}", source.display(), overlay.to_yaml()?);
+
+    if fs::read_to_string(copy).ok().as_deref() != Some(yaml.as_str()) {
+        if let Some(dir) = copy.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(copy, yaml).with_context(|_| format!("Unable to write {}", copy.display()))?;
+    }
+
+    Ok(())
+}
+
+impl ComposeFiles {
+    pub fn new<P: Into<PathBuf>>(project_file: P) -> Self {
+        ComposeFiles {
+            entries: vec![Entry::File(project_file.into())],
+            project_directory: None,
+        }
+    }
+
+    /// A project's compose file layered over the `compose_files` of its
+    /// config, with relative paths resolving against the project file's
+    /// directory.
+    pub fn layered<P: AsRef<Path>>(bases: &[PathBuf], project_file: P) -> Self {
+        let project_file = project_file.as_ref();
+
+        if bases.is_empty() {
+            return ComposeFiles::new(project_file);
+        }
+
+        let mut entries: Vec<Entry> = bases.iter().cloned().map(Entry::File).collect();
+        entries.push(Entry::File(project_file.to_path_buf()));
+
+        ComposeFiles {
+            entries,
+            project_directory: project_file.parent().map(Path::to_path_buf),
+        }
+    }
+
+    /// Adds a file devbox generated for the project, if it exists.
+    pub fn file<P: Into<PathBuf>>(self, file: P) -> Self {
+        self.push(Entry::File(file.into()))
+    }
+
+    /// Adds the service's overlay, if it exists and isn't already included.
+    pub fn overlay(self, service: &Service) -> Self {
+        self.push(Entry::File(service.devbox_compose_file()))
+    }
+
+    /// Adds the service's overlay as a copy with its relative paths made
+    /// absolute, kept in the `overlays` directory under `dir`, for sets whose
+    /// project directory isn't the overlay's own.
+    pub fn resolved_overlay(self, service: &Service, dir: &Path) -> Self {
+        self.push(Entry::Overlay {
+            source: service.devbox_compose_file(),
+            copy: dir.join(OVERLAYS_DIR).join(format!("{}.yml", service.name)),
+        })
+    }
+
+    /// Adds the port overrides `--remap` generated for the project in `dir`.
+    /// Compose rejects overrides for services the set doesn't define, so when
+    /// they name any, a copy without them is written to `copy` instead.
+    pub fn port_overrides(self, dir: &Path, copy: &Path) -> Self {
+        self.push(Entry::Ports {
+            source: ports::overrides_file(dir),
+            copy: copy.to_path_buf(),
+        })
+    }
+
+    fn push(mut self, entry: Entry) -> Self {
+        let path = entry.path();
+
+        if path.exists() && !self.entries.iter().any(|existing| existing.path() == path) {
+            self.entries.push(entry);
+        }
+
+        self
+    }
+
+    /// Where the copy of a project's port overrides for the set of a service,
+    /// or the whole project when there's none, is kept.
+    pub fn port_overrides_copy(dir: &Path, service: Option<&str>) -> PathBuf {
+        let name = match service {
+            Some(service) => format!("{}.ports.yml", service),
+            None => "ports.yml".to_owned(),
+        };
+
+        dir.join(OVERLAYS_DIR).join(name)
+    }
+
+    pub fn project_directory<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.project_directory = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// The files in the set, with the overlays and overrides that are given
+    /// to compose as copies listed as they are written.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.entries.iter().map(|entry| entry.path().to_path_buf()).collect()
+    }
+
+    /// Builds a command for `program` with a `-f` for every file in the set,
+    /// writing the copies compose is given for overlays and overrides.
+    pub fn command<S: AsRef<OsStr>>(&self, program: S) -> Result<Command> {
+        let mut cmd = Command::new(program);
+
+        for file in self.write_copies()? {
+            cmd.arg("-f").arg(file);
+        }
+
+        if let Some(ref dir) = self.project_directory {
+            cmd.arg("--project-directory").arg(dir);
+        }
+
+        Ok(cmd)
+    }
+
+    /// The files compose is given, with the copies written where they are
+    /// needed. An overlay devbox can't read is given as it is, in which case
+    /// its relative paths resolve against the project directory.
+    fn write_copies(&self) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+
+        for entry in &self.entries {
+            match *entry {
+                Entry::File(ref path) => files.push(path.clone()),
+                Entry::Overlay { ref source, ref copy } => match write_overlay_copy(source, copy) {
+                    Ok(()) => files.push(copy.clone()),
+                    Err(err) => {
+                        eprintln!(
+                            "{} Unable to make the paths in {} absolute, passing it as is: {}",
+                            "WARN".yellow(),
+                            source.display(),
+                            err
+                        );
+                        files.push(source.clone());
+                    }
+                },
+                Entry::Ports { ref source, ref copy } => {
+                    let services = self.service_names();
+                    let overridden = ComposeFile::read(source)?.compose.services;
+
+                    if overridden.keys().all(|service| services.contains(service)) {
+                        files.push(source.clone());
+                    } else if ports::write_overrides_for(source, &services, copy)? {
+                        files.push(copy.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// The names of the services the files other than the port overrides
+    /// define, read without the typed model so any valid file will do.
+    fn service_names(&self) -> BTreeSet<String> {
+        self.declared("services")
+    }
+
+    /// The names of the networks the files other than the port overrides
+    /// declare.
+    pub fn declared_networks(&self) -> BTreeSet<String> {
+        self.declared("networks")
+    }
+
+    fn declared(&self, key: &str) -> BTreeSet<String> {
+        self.entries
+            .iter()
+            .filter(|entry| !matches!(**entry, Entry::Ports { .. }))
+            .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+            .filter_map(|text| serde_yaml::from_str::<Value>(&text).ok())
+            .filter_map(|value| value.get(key).and_then(Value::as_mapping).cloned())
+            .flat_map(|names| names.into_iter().filter_map(|(name, _)| scalar(&name)))
+            .collect()
+    }
+
+    /// The names of the services defined across the set.
+    pub fn services(&self) -> Result<Vec<String>> {
+        let output = self.command("docker-compose")?
+            .arg("config")
+            .arg("--services")
+            .output()?;
+
+        if !output.status.success() {
+            Err(format_err!(
+                "Unable to list services: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))?
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect())
+    }
+
+    /// Parses every file in the set and merges them in order, with relative
+    /// paths made absolute against the project directory as compose does.
+    pub fn load(&self) -> Result<Compose> {
+        let mut compose = Compose::default();
+
+        for entry in &self.entries {
+            let file = ComposeFile::read(entry.path())?;
+            compose.merge(entry.prepare(file, &compose));
+        }
+
+        if let Some(dir) = self.directory() {
+            for service in compose.services.values_mut() {
+                service.resolve_paths(dir);
+            }
+        }
+
+        Ok(compose)
+    }
+
+    /// The directory relative paths and the `.env` file are found in.
+    pub fn directory(&self) -> Option<&Path> {
+        match self.project_directory {
+            Some(ref dir) => Some(dir),
+            None => self.entries.first().and_then(|entry| entry.path().parent()),
+        }
+    }
+
+    /// Produces the document compose would run with `env` as its
+    /// environment: every file interpolated, merged in order, and with its
+    /// relative paths made absolute. Variables whose values are secret
+    /// references are left as they are written.
+    pub fn render(&self, env: &BTreeMap<String, String>) -> Result<Rendered> {
+        let mut interpolator = Interpolator::new(self.directory(), env)?;
+        let mut compose = Compose::default();
+        let mut provenance = BTreeMap::new();
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            let path = entry.path();
+            let text = fs::read_to_string(path)
+                .with_context(|_| format!("Unable to read {}", path.display()))?;
+            let mut value = match text.trim() {
+                "" => Value::Mapping(Mapping::new()),
+                _ => serde_yaml::from_str(&text)
+                    .with_context(|_| format!("Unable to parse {}", path.display()))?,
+            };
+
+            interpolator
+                .interpolate(&mut value)
+                .with_context(|_| format!("Unable to interpolate {}", path.display()))?;
+
+            let replaced = replaced_keys(&text);
+            record_provenance(&mut provenance, &value, &replaced, index);
+
+            let file = ComposeFile {
+                path: path.to_path_buf(),
+                compose: serde_yaml::from_value(value)
+                    .with_context(|_| format!("Unable to parse {}", path.display()))?,
+                replaced,
+            };
+            compose.merge(entry.prepare(file, &compose));
+        }
+
+        if let Some(dir) = self.directory() {
+            for service in compose.services.values_mut() {
+                service.resolve_paths(dir);
+            }
+        }
+
+        Ok(Rendered {
+            compose,
+            files: self.files(),
+            provenance,
+            unset: interpolator.unset,
+        })
+    }
+}
+
+/// A rendered compose document, with the files each of its keys was set in.
+#[derive(Clone, Debug)]
+pub struct Rendered {
+    pub compose: Compose,
+    pub files: Vec<PathBuf>,
+    /// Indexes into `files` by key path, for the services and their keys,
+    /// the top-level volumes and networks, and the other top-level keys.
+    provenance: BTreeMap<Vec<String>, Vec<usize>>,
+    /// The variables that weren't set, which compose substitutes with an
+    /// empty string.
+    pub unset: BTreeSet<String>,
+}
+
+impl Rendered {
+    /// Keeps only the named services and the ones they depend on.
+    pub fn retain(&mut self, names: &[String]) -> Result<()> {
+        let mut kept = BTreeSet::new();
+        let mut pending: Vec<String> = names.to_vec();
+
+        while let Some(name) = pending.pop() {
+            let service = self
+                .compose
+                .services
+                .get(&name)
+                .ok_or_else(|| ServiceNotFound(name.clone()))?;
+
+            pending.extend(
+                service
+                    .depends_on
+                    .keys()
+                    .filter(|dependency| !kept.contains(*dependency))
+                    .cloned(),
+            );
+            kept.insert(name);
+        }
+
+        self.compose.services.retain(|name, _| kept.contains(name));
+
+        Ok(())
+    }
+
+    /// The document as YAML, headed by the files it came from. With
+    /// `explain`, each key is followed by a comment numbering the files that
+    /// set it.
+    pub fn to_yaml(&self, explain: bool) -> Result<String> {
+        let mut out = String::from("# Rendered by devbox from:\n");
+
+        for (index, file) in self.files.iter().enumerate() {
+            out.push_str(&format!("#   [{}] {}\n", index + 1, file.display()));
+        }
+
+        let yaml = self.compose.to_yaml()?;
+
+        if !explain {
+            out.push_str(&yaml);
+            return Ok(out);
+        }
+
+        let mut path: Vec<(usize, String)> = Vec::new();
+
+        for line in yaml.lines() {
+            out.push_str(line);
+
+            if let Some((indent, key)) = yaml_key(line) {
+                while path.last().is_some_and(|&(last, _)| last >= indent) {
+                    path.pop();
+                }
+                path.push((indent, key));
+
+                let keys: Vec<String> = path.iter().map(|(_, key)| key.clone()).collect();
+
+                if let Some(files) = self.provenance.get(&keys) {
+                    let files: Vec<String> =
+                        files.iter().map(|index| format!("[{}]", index + 1)).collect();
+                    out.push_str(&format!("  # {}", files.join(" ")));
+                }
+            }
+
+            out.push('\n');
+        }
+
+        Ok(out)
+    }
+}
+
+/// The indentation and key of a line of YAML written by serde_yaml, unless
+/// it's a list item or a continuation.
+fn yaml_key(line: &str) -> Option<(usize, String)> {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+
+    if trimmed.starts_with('-') || trimmed.starts_with('#') {
+        return None;
+    }
+
+    let key = if trimmed.starts_with('"') {
+        let end = trimmed.find("\":")?;
+        serde_json::from_str(&trimmed[..=end]).ok()?
+    } else {
+        let end = trimmed.find(": ").or_else(|| trimmed.strip_suffix(':').map(str::len))?;
+        trimmed[..end].to_owned()
+    };
+
+    Some((indent, key))
+}
+
+/// Notes `index` as a source of each key of a file. Lists and mappings add to
+/// what earlier files set, unless compose replaces them; anything else
+/// replaces it.
+fn record_provenance(
+    provenance: &mut BTreeMap<Vec<String>, Vec<usize>>,
+    value: &Value,
+    replaced: &[(String, String)],
+    index: usize,
+) {
+    let mut record = |path: Vec<String>, merged: bool| {
+        let files = provenance.entry(path).or_default();
+
+        if !merged {
+            files.clear();
+        }
+        if !files.contains(&index) {
+            files.push(index);
+        }
+    };
+
+    let top = match value.as_mapping() {
+        Some(top) => top,
+        None => return,
+    };
+
+    for (key, value) in top {
+        let key = match key.as_str() {
+            Some(key) => key,
+            None => continue,
+        };
+
+        let entries = match (key, value.as_mapping()) {
+            ("services", Some(entries)) | ("volumes", Some(entries)) | ("networks", Some(entries)) => {
+                entries
+            }
+            _ => {
+                record(vec![key.to_owned()], is_merged(key, value));
+                continue;
+            }
+        };
+
+        for (name, definition) in entries {
+            let name = match name.as_str() {
+                Some(name) => name,
+                None => continue,
+            };
+
+            record(vec![key.to_owned(), name.to_owned()], key == "services");
+
+            if key != "services" {
+                continue;
+            }
+
+            for (field, value) in definition.as_mapping().into_iter().flatten() {
+                let field = match field.as_str() {
+                    Some(field) => field,
+                    None => continue,
+                };
+                let tagged = replaced
+                    .iter()
+                    .any(|(service, key)| service == name && key == field);
+
+                record(
+                    vec![key.to_owned(), name.to_owned(), field.to_owned()],
+                    !tagged && is_merged(field, value),
+                );
+            }
+        }
+    }
+}
+
+fn is_merged(key: &str, value: &Value) -> bool {
+    match *value {
+        Value::Mapping(_) => true,
+        Value::Sequence(_) => !REPLACED_LISTS.contains(&key),
+        _ => false,
+    }
+}
+
+/// Substitutes variables into compose files as compose does: `$VAR`,
+/// `${VAR}`, `${VAR:-default}`, `${VAR-default}`, `${VAR:?error}`,
+/// `${VAR?error}`, `${VAR:+replacement}`, and `${VAR+replacement}`. A
+/// literal `$` stays escaped as `$$`, so the output means the same thing to
+/// compose.
+struct Interpolator {
+    vars: BTreeMap<String, String>,
+    /// Variables holding secret references, which are left as written.
+    hidden: BTreeSet<String>,
+    unset: BTreeSet<String>,
+}
+
+impl Interpolator {
+    /// The project directory's `.env`, overridden by the process's
+    /// environment, overridden by `env`.
+    fn new(dir: Option<&Path>, env: &BTreeMap<String, String>) -> Result<Self> {
+        let mut vars = BTreeMap::new();
+
+        if let Some(env_file) = dir.map(|dir| dir.join(".env")) {
+            if env_file.is_file() {
+                vars.extend(environment::read_env_file(&env_file)?);
+            }
+        }
+
+        vars.extend(::std::env::vars());
+
+        let mut hidden = BTreeSet::new();
+
+        for (key, value) in env {
+            if value.starts_with(SECRET_SCHEME) {
+                vars.remove(key);
+                hidden.insert(key.clone());
+            } else {
+                vars.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(Interpolator {
+            vars,
+            hidden,
+            unset: BTreeSet::new(),
+        })
+    }
+
+    fn interpolate(&mut self, value: &mut Value) -> Result<()> {
+        match *value {
+            Value::String(ref mut s) => *s = self.interpolate_str(s)?,
+            Value::Sequence(ref mut items) => {
+                for item in items.iter_mut() {
+                    self.interpolate(item)?;
+                }
+            }
+            Value::Mapping(ref mut mapping) => {
+                for (_, item) in mapping.iter_mut() {
+                    self.interpolate(item)?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn interpolate_str(&mut self, s: &str) -> Result<String> {
+        let mut out = String::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find('$') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+
+            if let Some(after) = after.strip_prefix('$') {
+                out.push_str("$$");
+                rest = after;
+            } else if let Some(inner) = after.strip_prefix('{') {
+                let end = closing_brace(inner)
+                    .ok_or_else(|| format_err!("Unterminated variable in `{}`", s))?;
+                out.push_str(&self.expand(&inner[..end], &rest[start..start + end + 3])?);
+                rest = &inner[end + 1..];
+            } else {
+                let len = variable_name_len(after);
+
+                if len == 0 {
+                    out.push_str("$$");
+                } else {
+                    out.push_str(&self.expand(&after[..len], &rest[start..start + len + 1])?);
+                }
+                rest = &after[len..];
+            }
+        }
+
+        out.push_str(rest);
+
+        Ok(out)
+    }
+
+    /// Expands the inside of `${...}`, or a bare name, written as `original`.
+    fn expand(&mut self, expression: &str, original: &str) -> Result<String> {
+        let len = variable_name_len(expression);
+        let (name, modifier) = expression.split_at(len);
+
+        if name.is_empty() {
+            Err(format_err!("Invalid variable `{}`", original))?
+        }
+
+        if self.hidden.contains(name) {
+            return Ok(original.to_owned());
+        }
+
+        // Values are escaped so the result can be handed to compose again.
+        let value = self.vars.get(name).map(|value| value.replace('$', "$$"));
+        let (operator, argument) = match modifier.find(|c| c != ':') {
+            Some(at) => modifier.split_at(at + 1),
+            None => (modifier, ""),
+        };
+        let empty = value.as_ref().is_none_or(String::is_empty);
+
+        let expanded = match operator {
+            "" => match value {
+                Some(value) => value,
+                None => {
+                    self.unset.insert(name.to_owned());
+                    String::new()
+                }
+            },
+            ":-" if empty => self.interpolate_str(argument)?,
+            "-" if value.is_none() => self.interpolate_str(argument)?,
+            ":-" | "-" => value.unwrap_or_default(),
+            ":?" if empty => Err(format_err!("{} is required: {}", name, argument))?,
+            "?" if value.is_none() => Err(format_err!("{} is required: {}", name, argument))?,
+            ":?" | "?" => value.unwrap_or_default(),
+            ":+" if !empty => self.interpolate_str(argument)?,
+            "+" if value.is_some() => self.interpolate_str(argument)?,
+            ":+" | "+" => String::new(),
+            _ => Err(format_err!("Invalid variable `{}`", original))?,
+        };
+
+        Ok(expanded)
+    }
+}
+
+fn variable_name_len(s: &str) -> usize {
+    s.char_indices()
+        .take_while(|&(index, c)| c == '_' || c.is_ascii_alphabetic() || (index > 0 && c.is_ascii_digit()))
+        .count()
+}
+
+/// The index of the `}` closing a `${`, allowing for nested variables.
+fn closing_brace(s: &str) -> Option<usize> {
+    let mut depth = 0;
+
+    for (index, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(index),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Keys whose values are merged deeper than a single replacement, but which
+/// devbox doesn't model, are merged as YAML: mappings key by key and lists
+/// appended, except these lists, which replace the ones before them.
+const REPLACED_LISTS: &[&str] = &["command", "entrypoint", "test"];
+
+/// A single compose file along with the `!override` and `!reset` tags on its
+/// service keys, which replace a key instead of merging it.
+#[derive(Clone, Debug)]
+pub struct ComposeFile {
+    pub path: PathBuf,
+    pub compose: Compose,
+    /// The service keys tagged `!override` or `!reset`, as
+    /// `(service, key)`.
+    pub replaced: Vec<(String, String)>,
+}
+
+impl ComposeFile {
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|_| format!("Unable to read {}", path.display()))?;
+        let compose = Compose::parse(&text)
+            .with_context(|_| format!("Unable to parse {}", path.display()))?;
+
+        Ok(ComposeFile {
+            path: path.to_path_buf(),
+            compose,
+            replaced: replaced_keys(&text),
+        })
+    }
+}
+
+/// A compose file in the v2 or v3 format, or the result of merging several.
+///
+/// The parts of a service that tie it to other services and to the host are
+/// typed; everything else is kept as YAML in `extra`. The short and long
+/// syntaxes of `environment`, `labels`, `networks`, and `depends_on` are
+/// read into maps.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Compose {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Value>,
+    #[serde(
+        default,
+        deserialize_with = "nullable_map",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub services: BTreeMap<String, ComposeService>,
+    #[serde(
+        default,
+        deserialize_with = "nullable_map",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub volumes: BTreeMap<String, Resource>,
+    #[serde(
+        default,
+        deserialize_with = "nullable_map",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub networks: BTreeMap<String, Resource>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl Compose {
+    pub fn parse(text: &str) -> Result<Self> {
+        // An empty file is an empty compose file, not an error.
+        if text.trim().is_empty() {
+            return Ok(Compose::default());
+        }
+
+        Ok(serde_yaml::from_str(text)?)
+    }
+
+    /// Merges a later file into this one, following compose's rules: a
+    /// service's single values are replaced, `ports` and `env_file` are
+    /// appended, `volumes` are merged by their target and `environment`,
+    /// `labels`, `networks`, and `depends_on` by name. Top-level volumes and
+    /// networks are replaced by name.
+    pub fn merge(&mut self, file: ComposeFile) {
+        let ComposeFile {
+            compose, replaced, ..
+        } = file;
+
+        if compose.version.is_some() {
+            self.version = compose.version;
+        }
+
+        for (name, service) in compose.services {
+            let replaced: Vec<&str> = replaced
+                .iter()
+                .filter(|&(service, _)| *service == name)
+                .map(|(_, key)| key.as_str())
+                .collect();
+
+            match self.services.get_mut(&name) {
+                Some(existing) => existing.merge(service, &replaced),
+                None => {
+                    self.services.insert(name, service);
+                }
+            }
+        }
+
+        self.volumes.extend(compose.volumes);
+        self.networks.extend(compose.networks);
+        merge_extra(&mut self.extra, compose.extra);
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        let yaml = serde_yaml::to_string(self)?;
+
+        Ok(format!("{}\n", yaml.trim_start_matches("---\n").trim_end()))
+    }
+
+    /// The names of the volumes or networks declared as external, as docker
+    /// knows them.
+    pub fn external_volumes(&self) -> Vec<String> {
+        external_names(&self.volumes)
+    }
+
+    pub fn external_networks(&self) -> Vec<String> {
+        external_names(&self.networks)
+    }
+}
+
+fn external_names(resources: &BTreeMap<String, Resource>) -> Vec<String> {
+    resources
+        .iter()
+        .filter(|&(_, resource)| resource.is_external())
+        .map(|(key, resource)| resource.docker_name(key).to_owned())
+        .collect()
+}
+
+/// A top-level volume or network.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Resource {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external: Option<External>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl Resource {
+    pub fn is_external(&self) -> bool {
+        match self.external {
+            Some(External::Flag(external)) => external,
+            Some(External::Named { .. }) => true,
+            None => false,
+        }
+    }
+
+    /// The name docker knows the resource by, which is `key` unless it's
+    /// renamed.
+    pub fn docker_name<'a>(&'a self, key: &'a str) -> &'a str {
+        match self.external {
+            Some(External::Named { ref name }) => name,
+            _ => self.name.as_ref().map_or(key, String::as_str),
+        }
+    }
+}
+
+/// `external: true`, or the older `external: { name: ... }`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum External {
+    Flag(bool),
+    Named { name: String },
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ComposeService {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<Build>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "key_values",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub environment: BTreeMap<String, Option<String>>,
+    #[serde(
+        default,
+        deserialize_with = "string_or_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub env_file: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<Port>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<ServiceVolume>,
+    #[serde(
+        default,
+        deserialize_with = "names_or_map",
+        serialize_with = "serialize_names_or_map",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub networks: BTreeMap<String, ServiceNetwork>,
+    #[serde(
+        default,
+        deserialize_with = "names_or_map",
+        serialize_with = "serialize_names_or_map",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub depends_on: BTreeMap<String, Dependency>,
+    #[serde(
+        default,
+        deserialize_with = "key_values",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub labels: BTreeMap<String, Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<Extends>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl ComposeService {
+    /// Merges a later definition of the service into this one. The keys in
+    /// `replaced` are replaced outright.
+    pub fn merge(&mut self, other: ComposeService, replaced: &[&str]) {
+        for key in replaced {
+            self.reset(key);
+        }
+
+        if other.image.is_some() {
+            self.image = other.image;
+        }
+        if other.command.is_some() {
+            self.command = other.command;
+        }
+        if other.entrypoint.is_some() {
+            self.entrypoint = other.entrypoint;
+        }
+        if other.container_name.is_some() {
+            self.container_name = other.container_name;
+        }
+        if other.extends.is_some() {
+            self.extends = other.extends;
+        }
+
+        self.build = match (self.build.take(), other.build) {
+            (Some(build), Some(other)) => Some(build.merge(other)),
+            (build, other) => other.or(build),
+        };
+
+        self.environment.extend(other.environment);
+        self.labels.extend(other.labels);
+        self.networks.extend(other.networks);
+        self.depends_on.extend(other.depends_on);
+
+        for file in other.env_file {
+            if !self.env_file.contains(&file) {
+                self.env_file.push(file);
+            }
+        }
+
+        for port in other.ports {
+            if !self.ports.contains(&port) {
+                self.ports.push(port);
+            }
+        }
+
+        for volume in other.volumes {
+            let target = volume.target().map(String::from);
+
+            match self
+                .volumes
+                .iter_mut()
+                .find(|existing| target.is_some() && existing.target() == target.as_deref())
+            {
+                Some(existing) => *existing = volume,
+                None => self.volumes.push(volume),
+            }
+        }
+
+        merge_extra(&mut self.extra, other.extra);
+    }
+
+    /// Clears a key, for `!override` and `!reset`.
+    fn reset(&mut self, key: &str) {
+        match key {
+            "image" => self.image = None,
+            "build" => self.build = None,
+            "command" => self.command = None,
+            "entrypoint" => self.entrypoint = None,
+            "container_name" => self.container_name = None,
+            "environment" => self.environment.clear(),
+            "env_file" => self.env_file.clear(),
+            "ports" => self.ports.clear(),
+            "volumes" => self.volumes.clear(),
+            "networks" => self.networks.clear(),
+            "depends_on" => self.depends_on.clear(),
+            "labels" => self.labels.clear(),
+            "extends" => self.extends = None,
+            key => {
+                self.extra.remove(key);
+            }
+        }
+    }
+
+    /// The build context, if the service is built rather than pulled.
+    pub fn build_context(&self) -> Option<&str> {
+        match self.build {
+            Some(Build::Context(ref context)) => Some(context),
+            Some(Build::Config(ref config)) => config.context.as_deref(),
+            None => None,
+        }
+    }
+
+    /// Makes the relative paths of the service absolute, resolving them
+    /// against `dir` as compose does against the project directory. Build
+    /// contexts that are URLs, and paths starting with a variable, are left
+    /// alone.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        let resolve = |path: &str| -> String {
+            let unresolvable = path.starts_with('~')
+                || path.starts_with('$')
+                || path.contains("://")
+                || path.starts_with("git@");
+
+            if unresolvable || Path::new(path).is_absolute() {
+                path.to_owned()
+            } else {
+                normalize(&dir.join(path)).to_string_lossy().into_owned()
+            }
+        };
+
+        match self.build {
+            Some(Build::Context(ref mut context)) => *context = resolve(context),
+            Some(Build::Config(ref mut config)) => {
+                if let Some(ref mut context) = config.context {
+                    *context = resolve(context);
+                }
+            }
+            None => {}
+        }
+
+        for file in self.env_file.iter_mut() {
+            *file = resolve(file);
+        }
+
+        if let Some(Extends::File {
+            file: Some(ref mut file),
+            ..
+        }) = self.extends
+        {
+            *file = resolve(file);
+        }
+
+        for volume in self.volumes.iter_mut() {
+            // Only sources starting with `.` are relative; anything else
+            // without a `/` is a named volume.
+            match *volume {
+                ServiceVolume::Short(ref mut spec) if spec.starts_with('.') => {
+                    *spec = match spec.split_once(':') {
+                        Some((source, rest)) => format!("{}:{}", resolve(source), rest),
+                        None => resolve(spec),
+                    };
+                }
+                ServiceVolume::Long(ref mut mount) => {
+                    if let Some(ref mut source) = mount.source {
+                        if source.starts_with('.') {
+                            *source = resolve(source);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// `build: ./dir` or `build: { context: ./dir, dockerfile: ... }`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Build {
+    Context(String),
+    Config(BuildConfig),
+}
+
+impl Build {
+    fn into_config(self) -> BuildConfig {
+        match self {
+            Build::Context(context) => BuildConfig {
+                context: Some(context),
+                ..BuildConfig::default()
+            },
+            Build::Config(config) => config,
+        }
+    }
+
+    fn merge(self, other: Build) -> Build {
+        let mut config = self.into_config();
+        let other = other.into_config();
+
+        if other.context.is_some() {
+            config.context = other.context;
+        }
+        if other.dockerfile.is_some() {
+            config.dockerfile = other.dockerfile;
+        }
+        config.args.extend(other.args);
+        merge_extra(&mut config.extra, other.extra);
+
+        Build::Config(config)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct BuildConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dockerfile: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "key_values",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub args: BTreeMap<String, Option<String>>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// A `ports:` entry: `"8080:80"`, a bare container port, or the long syntax.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Port {
+    Number(u64),
+    Short(String),
+    Long(Mapping),
+}
+
+/// A service's `volumes:` entry: `"source:target[:mode]"` or the long syntax.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ServiceVolume {
+    Short(String),
+    Long(VolumeMount),
+}
+
+impl ServiceVolume {
+    /// The path inside the container.
+    pub fn target(&self) -> Option<&str> {
+        match *self {
+            ServiceVolume::Short(ref spec) => {
+                let mut parts = spec.split(':');
+                let first = parts.next();
+                parts.next().or(first)
+            }
+            ServiceVolume::Long(ref mount) => mount.target.as_deref(),
+        }
+    }
+
+    /// The named volume or host path mounted, if any.
+    pub fn source(&self) -> Option<&str> {
+        match *self {
+            ServiceVolume::Short(ref spec) if spec.contains(':') => spec.split(':').next(),
+            ServiceVolume::Short(_) => None,
+            ServiceVolume::Long(ref mount) => mount.source.as_deref(),
+        }
+    }
+
+    /// Whether the source is a path on the host rather than a named volume.
+    pub fn is_bind(&self) -> bool {
+        match *self {
+            ServiceVolume::Long(VolumeMount {
+                kind: Some(ref kind),
+                ..
+            }) => kind == "bind",
+            _ => self.source().is_some_and(|source| {
+                source.starts_with('.') || source.starts_with('/') || source.starts_with('~')
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct VolumeMount {
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// A network a service joins, with the long syntax's settings.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ServiceNetwork {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// A service another one depends on, with the condition it waits for in the
+/// v2 format.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Dependency {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Extends {
+    Service(String),
+    File {
+        service: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        file: Option<String>,
+    },
+}
+
+/// Merges YAML the way compose does for keys devbox doesn't model.
+fn merge_extra(base: &mut BTreeMap<String, Value>, other: BTreeMap<String, Value>) {
+    for (key, value) in other {
+        match base.get_mut(&key) {
+            Some(existing) => merge_value(existing, value, &key),
+            None => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn merge_value(base: &mut Value, other: Value, key: &str) {
+    match (base, other) {
+        (&mut Value::Mapping(ref mut base), Value::Mapping(other)) => {
+            for (k, v) in other {
+                let inserted = match base.get_mut(&k) {
+                    Some(existing) => {
+                        merge_value(existing, v, k.as_str().unwrap_or_default());
+                        None
+                    }
+                    None => Some(v),
+                };
+
+                if let Some(v) = inserted {
+                    base.insert(k, v);
+                }
+            }
+        }
+        (&mut Value::Sequence(ref mut base), Value::Sequence(other)) if !REPLACED_LISTS.contains(&key) => {
+            for item in other {
+                if !base.contains(&item) {
+                    base.push(item);
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
+/// Finds the service keys tagged `!override` or `!reset`, which serde_yaml
+/// drops while parsing. Services written as flow mappings are looked into
+/// too, but tags on keys nested deeper than a service's own are ignored.
+fn replaced_keys(text: &str) -> Vec<(String, String)> {
+    let mut replaced = Vec::new();
+    let mut in_services = false;
+    let mut service_indent = None;
+    let mut key_indent = None;
+    let mut service: Option<String> = None;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+
+        // List items are never keys of a service.
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('-') {
+            continue;
+        }
+
+        let indent = line.len() - trimmed.len();
+        let (key, rest) = match split_key(trimmed) {
+            Some(parts) => parts,
+            None => continue,
+        };
+
+        if indent == 0 {
+            in_services = key == "services";
+            service_indent = None;
+            key_indent = None;
+            service = None;
+            continue;
+        }
+
+        if !in_services {
+            continue;
+        }
+
+        match service_indent {
+            Some(service_indent) if indent > service_indent => {
+                let key_indent = *key_indent.get_or_insert(indent);
+
+                if let (true, true, Some(service)) = (indent == key_indent, is_tagged(rest), service.as_ref()) {
+                    replaced.push((service.clone(), key.to_owned()));
+                }
+            }
+            _ => {
+                service_indent = Some(indent);
+                key_indent = None;
+                service = Some(key.to_owned());
+
+                for tagged in flow_tagged_keys(rest) {
+                    replaced.push((key.to_owned(), tagged));
+                }
+            }
+        }
+    }
+
+    replaced
+}
+
+/// Splits a mapping entry into its key, unquoted, and what follows the colon.
+fn split_key(entry: &str) -> Option<(&str, &str)> {
+    let (key, rest) = match entry.chars().next()? {
+        quote @ '"' | quote @ '\'' => {
+            let end = entry[1..].find(quote)? + 1;
+            (&entry[1..end], entry[end + 1..].trim_start().strip_prefix(':')?)
+        }
+        _ => {
+            let (key, rest) = entry.split_once(':')?;
+            (key.trim_end(), rest)
+        }
+    };
+
+    Some((key, rest.trim_start()))
+}
+
+fn is_tagged(value: &str) -> bool {
+    value.starts_with("!override") || value.starts_with("!reset")
+}
+
+/// The tagged keys of a flow mapping such as `{ image: x, ports: !reset [] }`,
+/// looking only at its own entries.
+fn flow_tagged_keys(value: &str) -> Vec<String> {
+    let inner = match (value.strip_prefix('{'), value.rfind('}')) {
+        (Some(_), Some(end)) if end > 0 => &value[1..end],
+        _ => return Vec::new(),
+    };
+
+    let mut entries = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+
+    for (i, c) in inner.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '[') | (None, '{') => depth += 1,
+            (None, ']') | (None, '}') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                entries.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(&inner[start..]);
+
+    entries
+        .into_iter()
+        .filter_map(|entry| split_key(entry.trim()))
+        .filter(|&(_, rest)| is_tagged(rest))
+        .map(|(key, _)| key.to_owned())
+        .collect()
+}
+
+fn nullable_map<'de, D, T>(deserializer: D) -> ::std::result::Result<BTreeMap<String, T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    let map: Option<BTreeMap<String, Option<T>>> = Deserialize::deserialize(deserializer)?;
+
+    Ok(map
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(key, value)| (key, value.unwrap_or_default()))
+        .collect())
+}
+
+fn string_or_list<'de, D>(deserializer: D) -> ::std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Deserialize::deserialize(deserializer)? {
+        Value::Null => Ok(Vec::new()),
+        Value::String(s) => Ok(vec![s]),
+        Value::Sequence(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s),
+                // The long syntax of newer compose versions.
+                item => item
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .map(String::from)
+                    .ok_or_else(|| de::Error::custom("expected a path")),
+            })
+            .collect(),
+        _ => Err(de::Error::custom("expected a string or a list of strings")),
+    }
+}
+
+/// Reads `["KEY=value", "KEY"]` or `{ KEY: value }` into a map. A key without
+/// a value is `None`.
+fn key_values<'de, D>(deserializer: D) -> ::std::result::Result<BTreeMap<String, Option<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Deserialize::deserialize(deserializer)? {
+        Value::Null => Ok(BTreeMap::new()),
+        Value::Sequence(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(item) => Ok(match item.split_once('=') {
+                    Some((key, value)) => (key.to_owned(), Some(value.to_owned())),
+                    None => (item, None),
+                }),
+                _ => Err(de::Error::custom("expected KEY=value")),
+            })
+            .collect(),
+        Value::Mapping(map) => map
+            .into_iter()
+            .map(|(key, value)| {
+                let key = scalar(&key).ok_or_else(|| de::Error::custom("expected a string key"))?;
+                Ok((key, scalar(&value)))
+            })
+            .collect(),
+        _ => Err(de::Error::custom("expected a list or a mapping")),
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref s) => Some(s.clone()),
+        Value::Number(ref n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Reads a list of names or a map of names to settings.
+fn names_or_map<'de, D, T>(deserializer: D) -> ::std::result::Result<BTreeMap<String, T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    match Deserialize::deserialize(deserializer)? {
+        Value::Null => Ok(BTreeMap::new()),
+        Value::Sequence(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(name) => Ok((name, T::default())),
+                _ => Err(de::Error::custom("expected a name")),
+            })
+            .collect(),
+        value @ Value::Mapping(_) => nullable_map(value).map_err(de::Error::custom),
+        _ => Err(de::Error::custom("expected a list or a mapping")),
+    }
+}
+
+/// Writes names without settings as a list, which every version of the
+/// format accepts.
+fn serialize_names_or_map<S, T>(map: &BTreeMap<String, T>, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize + Default + PartialEq,
+{
+    if map.values().all(|value| *value == T::default()) {
+        map.keys().collect::<Vec<_>>().serialize(serializer)
+    } else {
+        map.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn service(yaml: &str) -> ComposeService {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|&(service, key)| (service.to_owned(), key.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn replaced_keys_finds_tagged_service_keys() {
+        let text = r#"
+version: "3"
+services:
+  api:
+    image: api
+    ports: !reset []
+    "environment": !override
+      KEY: value
+  'search':
+    command: !override ["run"]
+volumes:
+  data: !reset {}
+"#;
+
+        assert_eq!(
+            replaced_keys(text),
+            pairs(&[("api", "ports"), ("api", "environment"), ("search", "command")])
+        );
+    }
+
+    #[test]
+    fn replaced_keys_ignores_list_items_and_nested_keys() {
+        let text = r#"
+services:
+  api:
+    volumes:
+    - data:/data
+    - type: !reset bind
+    build:
+      context: .
+      args: !override
+        KEY: value
+    labels:
+      - "tag: !reset"
+"#;
+
+        assert!(replaced_keys(text).is_empty());
+    }
+
+    #[test]
+    fn replaced_keys_reads_flow_mappings() {
+        let text = r#"
+services:
+  api: { image: api, ports: !reset [], build: { args: !override {} } }
+  "search": { "command": !override "run", environment: ["A=1, B: !reset"] }
+"#;
+
+        assert_eq!(
+            replaced_keys(text),
+            pairs(&[("api", "ports"), ("search", "command")])
+        );
+    }
+
+    #[test]
+    fn merge_follows_compose_rules() {
+        let mut base = service(
+            r#"
+image: api:1
+environment: { A: "1", B: "2" }
+ports: ["8080:80"]
+volumes: ["data:/data", "./src:/app"]
+depends_on: [db]
+"#,
+        );
+        let other = service(
+            r#"
+image: api:2
+environment: ["B=3", "C"]
+ports: ["8080:80", "9090:90"]
+volumes: ["./other:/app:ro"]
+depends_on: [cache]
+"#,
+        );
+
+        base.merge(other, &[]);
+
+        assert_eq!(base.image.as_deref(), Some("api:2"));
+        assert_eq!(
+            base.environment,
+            vec![
+                ("A".to_owned(), Some("1".to_owned())),
+                ("B".to_owned(), Some("3".to_owned())),
+                ("C".to_owned(), None),
+            ]
+            .into_iter()
+            .collect()
+        );
+        assert_eq!(
+            base.ports,
+            vec![Port::Short("8080:80".to_owned()), Port::Short("9090:90".to_owned())]
+        );
+        assert_eq!(
+            base.volumes,
+            vec![
+                ServiceVolume::Short("data:/data".to_owned()),
+                ServiceVolume::Short("./other:/app:ro".to_owned()),
+            ]
+        );
+        assert_eq!(base.depends_on.keys().collect::<Vec<_>>(), vec!["cache", "db"]);
+    }
+
+    #[test]
+    fn merge_replaces_reset_keys() {
+        let mut base = service("ports: [\"8080:80\"]\nenvironment: [A=1]\n");
+        let other = service("ports: [\"9090:90\"]\nenvironment: [B=2]\n");
+
+        base.merge(other, &["ports"]);
+
+        assert_eq!(base.ports, vec![Port::Short("9090:90".to_owned())]);
+        assert_eq!(base.environment.len(), 2);
+    }
+
+    #[test]
+    fn volume_target_reads_both_syntaxes() {
+        let volumes = service(
+            r#"
+volumes:
+  - ./src:/app:ro
+  - /data
+  - type: volume
+    source: data
+    target: /var/lib/data
+"#,
+        )
+        .volumes;
+        let targets: Vec<_> = volumes.iter().map(ServiceVolume::target).collect();
+
+        assert_eq!(targets, vec![Some("/app"), Some("/data"), Some("/var/lib/data")]);
+    }
+
+    #[test]
+    fn key_values_reads_lists_and_mappings() {
+        let list = service("environment: [\"A=1\", \"B=x=y\", C]\n").environment;
+        assert_eq!(list["A"].as_deref(), Some("1"));
+        assert_eq!(list["B"].as_deref(), Some("x=y"));
+        assert_eq!(list["C"], None);
+
+        let map = service("labels: { port: 8080, debug: true, empty: null, name: api }\n").labels;
+        assert_eq!(map["port"].as_deref(), Some("8080"));
+        assert_eq!(map["debug"].as_deref(), Some("true"));
+        assert_eq!(map["empty"], None);
+        assert_eq!(map["name"].as_deref(), Some("api"));
+    }
+
+    #[test]
+    fn names_or_map_reads_lists_and_mappings() {
+        let listed = service("networks: [front, back]\ndepends_on: [db]\n");
+        assert_eq!(listed.networks.keys().collect::<Vec<_>>(), vec!["back", "front"]);
+        assert_eq!(listed.depends_on["db"], Dependency::default());
+
+        let mapped = service(
+            r#"
+networks:
+  front:
+  back: { aliases: [api] }
+depends_on:
+  db: { condition: service_healthy }
+"#,
+        );
+        assert_eq!(mapped.networks["front"], ServiceNetwork::default());
+        assert_eq!(mapped.networks["back"].aliases, vec!["api"]);
+        assert_eq!(
+            mapped.depends_on["db"].condition.as_deref(),
+            Some("service_healthy")
+        );
+    }
+
+    #[test]
+    fn load_resolves_relative_paths_against_the_project_directory() {
+        let dir = TempDir::new("devbox").unwrap();
+        let project_file = dir.path().join("docker-compose.yml");
+        fs::write(
+            &project_file,
+            r#"
+services:
+  api:
+    build: ./api
+    env_file: [.env, "$${HOME}/.env"]
+    volumes: ["./src:/app", "data:/data", "/etc:/etc"]
+"#,
+        )
+        .unwrap();
+
+        let compose = ComposeFiles::new(&project_file).load().unwrap();
+        let api = &compose.services["api"];
+        let path = |rel: &str| dir.path().join(rel).to_string_lossy().into_owned();
+
+        assert_eq!(api.build_context(), Some(path("api").as_str()));
+        assert_eq!(api.env_file, vec![path(".env"), "$${HOME}/.env".to_owned()]);
+        assert_eq!(
+            api.volumes,
+            vec![
+                ServiceVolume::Short(format!("{}:/app", path("src"))),
+                ServiceVolume::Short("data:/data".to_owned()),
+                ServiceVolume::Short("/etc:/etc".to_owned()),
+            ]
+        );
+    }
+}
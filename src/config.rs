@@ -0,0 +1,750 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use colored::*;
+use failure::ResultExt;
+use serde_json;
+use toml;
+use toml::value::Table;
+use toml_edit::{self, DocumentMut, InlineTable, Item, TableLike};
+
+use errors::*;
+
+/// Arrays that are concatenated across layers rather than replaced, with
+/// duplicates dropped.
+const CONCATENATED: &[&str] = &["compose_files", "env_files", "volumes"];
+
+/// Arrays holding paths relative to the file that declares them.
+const RELATIVE_PATHS: &[&str] = &["compose_files", "env_files"];
+
+/// A devbox config file merged with the files it `extends` and `include`s.
+///
+/// ```toml
+/// extends = "../base/config.toml"
+/// include = ["shared/infra.toml"]
+/// ```
+///
+/// The base from `extends` is loaded first, then each `include` in order, then
+/// the file itself, each layer overriding the ones before it:
+///
+/// * tables such as `env`, `networks`, and `hooks` are merged key by key
+/// * a service in `services` replaces the whole earlier definition
+/// * `volumes`, `env_files`, and `compose_files` are concatenated
+/// * `tasks` are merged by name
+/// * any other value replaces the earlier one
+///
+/// Paths in `extends`, `include`, `env_files`, and `compose_files` are
+/// relative to the file that declares them.
+///
+/// Every file's strings are interpolated as it's read, before its paths are
+/// resolved, so `${VAR}` can start a path:
+///
+/// * `${VAR}` and `${VAR:-default}` are the environment variable `VAR`; an
+///   unset `${VAR}` is empty, with a warning, as it is in compose
+/// * `${VAR:?message}` fails with `message` when `VAR` is unset or empty
+/// * `${name}` for dotted names like `project.name` is the value in
+///   `variables`
+/// * `$$` is a literal `$`, so `$${VAR}` is left as `${VAR}`, which is how
+///   shell snippets such as a task's `exec` refer to their own variables
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub value: toml::Value,
+    /// Every file that went into the config, in the order they were merged.
+    pub files: Vec<PathBuf>,
+    /// The files that set each value, keyed by its dotted path.
+    provenance: BTreeMap<String, Vec<PathBuf>>,
+    variables: BTreeMap<String, String>,
+}
+
+impl Config {
+    pub fn load(path: &Path, variables: &BTreeMap<String, String>) -> Result<Self> {
+        let mut config = Config {
+            value: toml::Value::Table(Table::new()),
+            files: Vec::new(),
+            provenance: BTreeMap::new(),
+            variables: variables.clone(),
+        };
+
+        config.layer(path, &mut Vec::new(), false)?;
+
+        Ok(config)
+    }
+
+    /// Merges a file of personal overrides over the config, if it exists.
+    /// Unlike other layers, it merges into services field by field, so it can
+    /// change a service's `path` without repeating the rest of it.
+    pub fn overlay(&mut self, path: &Path) -> Result<()> {
+        if path.exists() {
+            self.layer(path, &mut Vec::new(), true)?;
+        }
+
+        Ok(())
+    }
+
+    /// The value at a dotted path such as `services.api.path`.
+    pub fn get(&self, path: &str) -> Option<&toml::Value> {
+        path.split('.')
+            .try_fold(&self.value, |value, key| value.get(key))
+    }
+
+    /// The files that set the value at a dotted path, or any value under it.
+    pub fn provenance(&self, path: &str) -> Vec<&Path> {
+        let prefix = format!("{}.", path);
+        let mut files: Vec<&Path> = Vec::new();
+
+        for (key, sources) in &self.provenance {
+            if key == path || key.starts_with(&prefix) {
+                for file in sources {
+                    if !files.contains(&file.as_path()) {
+                        files.push(file);
+                    }
+                }
+            }
+        }
+
+        files
+    }
+
+    /// The merged config as TOML, with a comment on every value naming the
+    /// files it came from.
+    pub fn to_annotated_toml(&self) -> String {
+        let mut out = String::from("# Resolved from:\n");
+
+        for file in &self.files {
+            out.push_str(&format!("#   {}\n", file.display()));
+        }
+
+        if let Some(table) = self.value.as_table() {
+            self.write_table(&mut out, &[], table);
+        }
+
+        out
+    }
+
+    fn write_table(&self, out: &mut String, path: &[&str], table: &Table) {
+        for (key, value) in table.iter().filter(|&(_, v)| !v.is_table()) {
+            let dotted = dotted(path, key);
+            let files: Vec<String> = self
+                .provenance(&dotted)
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect();
+
+            out.push_str(&format!(
+                "{} = {}  # {}\n",
+                quote_key(key),
+                format_value(value),
+                files.join(", ")
+            ));
+        }
+
+        for (key, value) in table {
+            if let toml::Value::Table(ref table) = *value {
+                let mut path = path.to_vec();
+                path.push(key);
+
+                // Tables of tables only need headers for the tables inside.
+                if table.is_empty() || table.values().any(|v| !v.is_table()) {
+                    let header: Vec<String> = path.iter().map(|key| quote_key(key)).collect();
+                    out.push_str(&format!("\n[{}]\n", header.join(".")));
+                }
+
+                self.write_table(out, &path, table);
+            }
+        }
+    }
+
+    /// Merges the file at `path`, and the files it refers to, into the config.
+    /// `stack` holds the files being loaded, to catch cycles.
+    fn layer(&mut self, path: &Path, stack: &mut Vec<PathBuf>, overrides: bool) -> Result<()> {
+        let canonical = path
+            .canonicalize()
+            .with_context(|_| format!("Unable to find config file {}", path.display()))?;
+
+        if stack.contains(&canonical) {
+            Err(format_err!(
+                "Config file {} extends or includes itself",
+                path.display()
+            ))?
+        }
+
+        let contents = fs::read_to_string(path)
+            .with_context(|_| format!("Unable to read config file {}", path.display()))?;
+        let mut table = toml::from_str::<Table>(&contents)
+            .with_context(|_| format!("Unable to parse config file {}", path.display()))?;
+        interpolate_table(&mut table, &self.variables)
+            .with_context(|_| format!("Unable to load config file {}", path.display()))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+        stack.push(canonical);
+
+        if let Some(extends) = table.remove("extends") {
+            let base = extends
+                .as_str()
+                .ok_or_else(|| format_err!("`extends` in {} must be a path", path.display()))?;
+            self.layer(&normalize(&dir.join(base)), stack, overrides)?;
+        }
+
+        if let Some(include) = table.remove("include") {
+            let files = include.try_into::<Vec<String>>().with_context(|_| {
+                format!("`include` in {} must be a list of paths", path.display())
+            })?;
+
+            for file in files {
+                self.layer(&normalize(&dir.join(file)), stack, overrides)?;
+            }
+        }
+
+        stack.pop();
+
+        resolve_paths(&mut table, dir);
+
+        if let toml::Value::Table(ref mut base) = self.value {
+            merge(base, table, "", path, overrides, &mut self.provenance);
+        }
+
+        self.files.push(path.to_path_buf());
+
+        Ok(())
+    }
+}
+
+/// A config file edited in place, keeping its comments and formatting.
+#[derive(Clone, Debug)]
+pub struct ConfigFile {
+    path: PathBuf,
+    /// The contents when opened, or `None` if the file didn't exist.
+    original: Option<String>,
+    document: DocumentMut,
+}
+
+impl ConfigFile {
+    /// Opens a config file, or starts an empty one if it doesn't exist yet.
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let original = if path.exists() {
+            Some(
+                fs::read_to_string(&path)
+                    .with_context(|_| format!("Unable to read config file {}", path.display()))?,
+            )
+        } else {
+            None
+        };
+        let document = original
+            .as_deref()
+            .unwrap_or_default()
+            .parse::<DocumentMut>()
+            .with_context(|_| format!("Unable to parse config file {}", path.display()))?;
+
+        Ok(ConfigFile {
+            path,
+            original,
+            document,
+        })
+    }
+
+    /// Sets the value at a dotted path, creating the tables leading to it.
+    /// Booleans, integers, arrays, inline tables, and quoted strings are read
+    /// as TOML, such as `true` or `["a", "b"]`; anything else is a string, so
+    /// a version like `1.10` isn't turned into the float `1.1`.
+    pub fn set(&mut self, path: &str, value: &str) -> Result<()> {
+        let mut keys: Vec<&str> = path.split('.').collect();
+        let last = keys
+            .pop()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| format_err!("Invalid key `{}`", path))?;
+
+        let mut value = match value.parse::<toml_edit::Value>() {
+            Ok(toml_edit::Value::Float(_)) | Ok(toml_edit::Value::Datetime(_)) | Err(_) => {
+                toml_edit::Value::from(value)
+            }
+            Ok(parsed) => parsed,
+        };
+
+        let mut table: &mut dyn TableLike = self.document.as_table_mut();
+        let mut inline = false;
+
+        for key in keys {
+            if table.get(key).is_none() {
+                let item = if inline {
+                    Item::Value(toml_edit::Value::InlineTable(InlineTable::new()))
+                } else {
+                    let mut table = toml_edit::Table::new();
+                    table.set_implicit(true);
+                    Item::Table(table)
+                };
+                table.insert(key, item);
+            }
+
+            let item = table
+                .get_mut(key)
+                .ok_or_else(|| format_err!("Invalid key `{}`", path))?;
+            inline = inline || item.is_inline_table();
+            table = item
+                .as_table_like_mut()
+                .ok_or_else(|| format_err!("`{}` in `{}` is not a table", key, path))?;
+        }
+
+        // Keep any comment trailing the value being replaced.
+        if let Some(existing) = table.get(last).and_then(|item| item.as_value()) {
+            *value.decor_mut() = existing.decor().clone();
+        }
+
+        table.insert(last, Item::Value(value));
+
+        Ok(())
+    }
+
+    /// Adds a service to the `[services]` table.
+    pub fn add_service(&mut self, name: &str, git: Option<&str>, path: Option<&str>) -> Result<()> {
+        let services = self
+            .document
+            .entry("services")
+            .or_insert_with(|| Item::Table(toml_edit::Table::new()))
+            .as_table_like_mut()
+            .ok_or_else(|| format_err!("`services` is not a table"))?;
+
+        if services.contains_key(name) {
+            Err(format_err!("Service {} already exists", name))?
+        }
+
+        let mut service = InlineTable::new();
+        if let Some(git) = git {
+            service.insert("git", git.into());
+        }
+        if let Some(path) = path {
+            service.insert("path", path.into());
+        }
+
+        services.insert(name, Item::Value(toml_edit::Value::InlineTable(service)));
+
+        Ok(())
+    }
+
+    /// Writes the file, then puts the original contents back if `check`
+    /// rejects the result.
+    pub fn save_checked<F: FnOnce() -> Result<()>>(&self, check: F) -> Result<()> {
+        fs::write(&self.path, self.document.to_string())?;
+
+        if let Err(err) = check() {
+            self.restore()?;
+            Err(err)?
+        }
+
+        Ok(())
+    }
+
+    /// Puts back the contents the file had when it was opened, removing it
+    /// if it didn't exist.
+    pub fn restore(&self) -> Result<()> {
+        match self.original {
+            Some(ref original) => fs::write(&self.path, original)?,
+            None => fs::remove_file(&self.path)?,
+        }
+
+        Ok(())
+    }
+}
+
+fn merge(
+    base: &mut Table,
+    layer: Table,
+    path: &str,
+    file: &Path,
+    overrides: bool,
+    provenance: &mut BTreeMap<String, Vec<PathBuf>>,
+) {
+    for (key, value) in layer {
+        let dotted = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", path, key)
+        };
+
+        match (base.get_mut(&key), value) {
+            (Some(&mut toml::Value::Table(ref mut base)), toml::Value::Table(layer))
+                if path != "services" || overrides =>
+            {
+                merge(base, layer, &dotted, file, overrides, provenance);
+                continue;
+            }
+            (Some(&mut toml::Value::Array(ref mut base)), toml::Value::Array(layer))
+                if CONCATENATED.contains(&key.as_str()) =>
+            {
+                for value in layer {
+                    if !base.contains(&value) {
+                        base.push(value);
+                    }
+                }
+                provenance.entry(dotted).or_default().push(file.to_path_buf());
+                continue;
+            }
+            (Some(&mut toml::Value::Array(ref mut base)), toml::Value::Array(layer))
+                if key == "tasks" =>
+            {
+                for task in layer {
+                    let name = task.get("name").cloned();
+                    let existing = base
+                        .iter_mut()
+                        .find(|t| name.is_some() && t.get("name") == name.as_ref());
+
+                    match existing {
+                        Some(existing) => *existing = task,
+                        None => base.push(task),
+                    }
+                }
+                provenance.entry(dotted).or_default().push(file.to_path_buf());
+                continue;
+            }
+            (_, value) => {
+                let prefix = format!("{}.", dotted);
+                provenance.retain(|k, _| *k != dotted && !k.starts_with(&prefix));
+                record(&value, &dotted, file, provenance);
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn record(
+    value: &toml::Value,
+    path: &str,
+    file: &Path,
+    provenance: &mut BTreeMap<String, Vec<PathBuf>>,
+) {
+    match *value {
+        toml::Value::Table(ref table) => {
+            for (key, value) in table {
+                record(value, &format!("{}.{}", path, key), file, provenance);
+            }
+        }
+        _ => {
+            provenance.insert(path.to_owned(), vec![file.to_path_buf()]);
+        }
+    }
+}
+
+fn interpolate_table(table: &mut Table, variables: &BTreeMap<String, String>) -> Result<()> {
+    for (key, value) in table.iter_mut() {
+        interpolate_value(value, key, variables)?;
+    }
+
+    Ok(())
+}
+
+fn interpolate_value(
+    value: &mut toml::Value,
+    path: &str,
+    variables: &BTreeMap<String, String>,
+) -> Result<()> {
+    match *value {
+        toml::Value::String(ref mut s) => {
+            *s = interpolate_str(s, variables)
+                .with_context(|_| format!("Unable to interpolate `{}`", path))?;
+        }
+        toml::Value::Array(ref mut values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                interpolate_value(value, &format!("{}[{}]", path, i), variables)?;
+            }
+        }
+        toml::Value::Table(ref mut table) => {
+            for (key, value) in table.iter_mut() {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                interpolate_value(value, &path, variables)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn interpolate_str(s: &str, variables: &BTreeMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("$$") {
+            out.push('$');
+            rest = &rest[2..];
+        } else if rest.starts_with("${") {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| format_err!("Unterminated `${{` in \"{}\"", s))?;
+            let expression = &rest[2..end];
+            let (name, fallback) = match expression.find(":-") {
+                Some(i) => (&expression[..i], Fallback::Default(&expression[i + 2..])),
+                None => match expression.find(":?") {
+                    Some(i) => (&expression[..i], Fallback::Error(&expression[i + 2..])),
+                    None => (expression, Fallback::Empty),
+                },
+            };
+
+            out.push_str(&lookup(name, fallback, variables)?);
+            rest = &rest[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+/// What an environment variable that's unset or empty stands for.
+enum Fallback<'a> {
+    /// An empty string, warning when the variable is unset: `${VAR}`.
+    Empty,
+    /// `${VAR:-default}`.
+    Default(&'a str),
+    /// A failure with a message: `${VAR:?message}`.
+    Error(&'a str),
+}
+
+fn lookup(name: &str, fallback: Fallback, variables: &BTreeMap<String, String>) -> Result<String> {
+    if name.contains('.') {
+        return variables
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format_err!("Unknown variable `${{{}}}`", name));
+    }
+
+    match (env::var(name), fallback) {
+        (Ok(ref value), Fallback::Default(default)) if value.is_empty() => Ok(default.to_owned()),
+        (Ok(ref value), Fallback::Error(message)) if value.is_empty() => {
+            Err(format_err!("Environment variable {} is empty: {}", name, message))
+        }
+        (Ok(value), _) => Ok(value),
+        (Err(_), Fallback::Default(default)) => Ok(default.to_owned()),
+        (Err(_), Fallback::Error(message)) => {
+            Err(format_err!("Environment variable {} is not set: {}", name, message))
+        }
+        (Err(_), Fallback::Empty) => {
+            eprintln!(
+                "{} Environment variable {} is not set; substituting an empty string",
+                "WARN".yellow(),
+                name
+            );
+            Ok(String::new())
+        }
+    }
+}
+
+/// Makes the relative paths a file declares relative to the file's directory
+/// instead, so they still point at the same files once merged.
+fn resolve_paths(table: &mut Table, dir: &Path) {
+    for key in RELATIVE_PATHS {
+        if let Some(&mut toml::Value::Array(ref mut paths)) = table.get_mut(*key) {
+            for path in paths.iter_mut() {
+                if let Some(resolved) = path.as_str().map(|p| normalize(&dir.join(p))) {
+                    *path = toml::Value::String(resolved.to_string_lossy().into_owned());
+                }
+            }
+        }
+    }
+}
+
+/// Drops the `.` and `..` components of a path without touching the disk.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component.as_os_str()),
+        }
+    }
+
+    normalized
+}
+
+fn dotted(path: &[&str], key: &str) -> String {
+    let mut parts = path.to_vec();
+    parts.push(key);
+    parts.join(".")
+}
+
+pub(crate) fn quote_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if bare {
+        key.to_owned()
+    } else {
+        serde_json::to_string(key).unwrap_or_default()
+    }
+}
+
+/// Formats a value on a single line, as it would be written after `=`, since
+/// `toml` only writes tables inside arrays as sections.
+pub fn format_value(value: &toml::Value) -> String {
+    match *value {
+        toml::Value::Array(ref values) => {
+            let values: Vec<String> = values.iter().map(format_value).collect();
+            format!("[{}]", values.join(", "))
+        }
+        toml::Value::Table(ref table) => {
+            let entries: Vec<String> = table
+                .iter()
+                .map(|(key, value)| format!("{} = {}", quote_key(key), format_value(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        ref value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    const CONFIG: &str = r#"
+[services]
+api = { git = "git@example.com:api", path = "/src/api" }
+search = { git = "git@example.com:search" }
+"#;
+
+    /// Loads `config` from a `config.toml` with `local` as its
+    /// `config.local.toml`.
+    fn load(dir: &TempDir, config: &str, local: &str) -> Config {
+        let path = dir.path().join("config.toml");
+        let local_path = dir.path().join("config.local.toml");
+
+        fs::write(&path, config).unwrap();
+        fs::write(&local_path, local).unwrap();
+
+        let mut config = Config::load(&path, &BTreeMap::new()).unwrap();
+        config.overlay(&local_path).unwrap();
+        config
+    }
+
+    fn string<'a>(config: &'a Config, path: &str) -> Option<&'a str> {
+        config.get(path).and_then(|value| value.as_str())
+    }
+
+    #[test]
+    fn overlay_overrides_service_fields_one_by_one() {
+        let dir = TempDir::new("devbox").unwrap();
+        let config = load(&dir, CONFIG, "[services.api]\npath = \"/home/me/api\"\n");
+
+        assert_eq!(string(&config, "services.api.path"), Some("/home/me/api"));
+        assert_eq!(string(&config, "services.api.git"), Some("git@example.com:api"));
+        assert_eq!(string(&config, "services.search.git"), Some("git@example.com:search"));
+    }
+
+    #[test]
+    fn overlay_adds_services() {
+        let dir = TempDir::new("devbox").unwrap();
+        let config = load(&dir, CONFIG, "[services.scratch]\npath = \"/home/me/scratch\"\n");
+
+        assert_eq!(string(&config, "services.scratch.path"), Some("/home/me/scratch"));
+        assert!(config.get("services.api").is_some());
+        assert!(config.get("services.search").is_some());
+    }
+
+    #[test]
+    fn overlay_disables_services_without_repeating_them() {
+        let dir = TempDir::new("devbox").unwrap();
+        let config = load(&dir, CONFIG, "[services.search]\ndisabled = true\n");
+
+        assert_eq!(
+            config.get("services.search.disabled").and_then(|value| value.as_bool()),
+            Some(true)
+        );
+        assert_eq!(string(&config, "services.search.git"), Some("git@example.com:search"));
+    }
+
+    #[test]
+    fn overlay_is_noted_in_provenance() {
+        let dir = TempDir::new("devbox").unwrap();
+        let config = load(&dir, CONFIG, "[services.api]\npath = \"/home/me/api\"\n");
+        let path = dir.path().join("config.toml");
+        let local_path = dir.path().join("config.local.toml");
+
+        assert_eq!(config.provenance("services.api.path"), vec![local_path.as_path()]);
+        assert_eq!(config.provenance("services.api.git"), vec![path.as_path()]);
+        assert_eq!(
+            config.provenance("services.api"),
+            vec![path.as_path(), local_path.as_path()]
+        );
+
+        let annotated = config.to_annotated_toml();
+        assert!(annotated.contains(&format!(
+            "path = \"/home/me/api\"  # {}",
+            local_path.display()
+        )));
+        assert!(annotated.contains(&format!(
+            "git = \"git@example.com:api\"  # {}",
+            path.display()
+        )));
+    }
+
+    #[test]
+    fn later_layers_replace_whole_services() {
+        let dir = TempDir::new("devbox").unwrap();
+        let base = dir.path().join("base.toml");
+        fs::write(&base, CONFIG).unwrap();
+
+        let config = load(
+            &dir,
+            "extends = \"base.toml\"\n[services]\napi = { path = \"/other/api\" }\n",
+            "",
+        );
+
+        assert_eq!(string(&config, "services.api.path"), Some("/other/api"));
+        assert_eq!(config.get("services.api.git"), None);
+    }
+
+    #[test]
+    fn set_reads_plain_toml_values_and_strings_otherwise() {
+        let dir = TempDir::new("devbox").unwrap();
+        let mut file = ConfigFile::open(dir.path().join("config.toml")).unwrap();
+
+        file.set("a.enabled", "true").unwrap();
+        file.set("a.count", "3").unwrap();
+        file.set("a.list", "[\"x\", \"y\"]").unwrap();
+        file.set("a.quoted", "\"true\"").unwrap();
+        file.set("a.version", "1.10").unwrap();
+        file.set("a.date", "2024-01-02").unwrap();
+        file.set("a.word", "develop").unwrap();
+
+        let value: toml::Value = toml::from_str(&file.document.to_string()).unwrap();
+
+        assert_eq!(value["a"]["enabled"], toml::Value::Boolean(true));
+        assert_eq!(value["a"]["count"], toml::Value::Integer(3));
+        assert_eq!(value["a"]["list"].as_array().map(Vec::len), Some(2));
+        assert_eq!(value["a"]["quoted"].as_str(), Some("true"));
+        assert_eq!(value["a"]["version"].as_str(), Some("1.10"));
+        assert_eq!(value["a"]["date"].as_str(), Some("2024-01-02"));
+        assert_eq!(value["a"]["word"].as_str(), Some("develop"));
+    }
+
+    #[test]
+    fn unset_variables_are_empty_unless_required() {
+        let variables = BTreeMap::new();
+        env::remove_var("DEVBOX_TEST_UNSET");
+
+        assert_eq!(interpolate_str("a${DEVBOX_TEST_UNSET}b", &variables).unwrap(), "ab");
+        assert_eq!(interpolate_str("${DEVBOX_TEST_UNSET:-x}", &variables).unwrap(), "x");
+
+        let error = interpolate_str("${DEVBOX_TEST_UNSET:?set it}", &variables).unwrap_err();
+        assert!(error.to_string().contains("set it"));
+    }
+}
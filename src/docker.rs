@@ -0,0 +1,280 @@
+use std::fmt;
+use std::process::{Command, Stdio};
+
+use errors::*;
+
+/// The label `docker-compose` puts on everything it creates for a project.
+pub const PROJECT_LABEL: &str = "com.docker.compose.project";
+
+/// The image of the throwaway containers that read and write volume contents.
+pub(crate) const HELPER_IMAGE: &str = "alpine:3";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Container,
+    Image,
+    Volume,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Kind::Container => "container",
+            Kind::Image => "image",
+            Kind::Volume => "volume",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// A container, image, or volume belonging to a project.
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub kind: Kind,
+    pub id: String,
+    /// The container name, `repository:tag` of an image (`<none>:<none>` when
+    /// dangling), or volume name.
+    pub name: String,
+    /// The disk space removing it frees, in bytes, when known.
+    pub size: Option<u64>,
+}
+
+impl Resource {
+    pub fn volume(name: &str) -> Self {
+        Resource {
+            kind: Kind::Volume,
+            id: name.to_owned(),
+            name: name.to_owned(),
+            size: None,
+        }
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        let mut cmd = Command::new("docker");
+
+        match self.kind {
+            Kind::Container => cmd.args(["rm", &self.id]),
+            // Images are removed by name when they have one, so that other
+            // tags of the same image are left alone.
+            Kind::Image if !self.name.contains("<none>") => cmd.args(["rmi", &self.name]),
+            Kind::Image => cmd.args(["rmi", &self.id]),
+            Kind::Volume => cmd.args(["volume", "rm", &self.name]),
+        };
+
+        run(&mut cmd, &format!("remove {} {}", self.kind, self.name))
+    }
+}
+
+/// The project's containers that aren't running.
+pub fn project_containers(project: &str) -> Result<Vec<Resource>> {
+    let containers = lines(
+        Command::new("docker")
+            .args(["ps", "-a", "--size"])
+            .args(["--filter", "status=created", "--filter", "status=exited"])
+            .args(["--format", "{{.ID}}\t{{.Names}}\t{{.Size}}", "--filter"])
+            .arg(project_filter(project)),
+    )?;
+
+    Ok(containers
+        .iter()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let id = fields.next()?;
+            let name = fields.next()?;
+            // The size reads like "2B (virtual 142MB)"; only the first part
+            // belongs to the container alone.
+            let size = fields
+                .next()
+                .and_then(|size| parse_size(size.split(' ').next()?));
+
+            Some(Resource {
+                kind: Kind::Container,
+                id: id.to_owned(),
+                name: name.to_owned(),
+                size,
+            })
+        })
+        .collect())
+}
+
+/// The images `docker-compose` built for the project's `services`. Newer
+/// versions label them with the project; version 1 doesn't, but names them
+/// `<project>_<service>`.
+pub fn project_images(project: &str, services: &[String]) -> Result<Vec<Resource>> {
+    let format = "{{.ID}}\t{{.Repository}}:{{.Tag}}\t{{.Size}}";
+    let mut images = lines(
+        Command::new("docker")
+            .args(["images", "--format", format])
+            .arg("--filter")
+            .arg(project_filter(project)),
+    )?;
+
+    let prefix = legacy_project_name(project);
+    let unlabelled = lines(Command::new("docker").args(["images", "--format", format]))?;
+
+    for line in unlabelled {
+        let named = line
+            .split('\t')
+            .nth(1)
+            .and_then(|name| name.rsplit_once(':'))
+            .is_some_and(|(repository, _)| {
+                services
+                    .iter()
+                    .any(|service| repository == format!("{}_{}", prefix, service))
+            });
+
+        if named && !images.contains(&line) {
+            images.push(line);
+        }
+    }
+
+    Ok(images
+        .iter()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+
+            Some(Resource {
+                kind: Kind::Image,
+                id: fields.next()?.to_owned(),
+                name: fields.next()?.to_owned(),
+                size: fields.next().and_then(parse_size),
+            })
+        })
+        .collect())
+}
+
+/// The project name as compose version 1 uses it in image names.
+fn legacy_project_name(project: &str) -> String {
+    project
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect()
+}
+
+/// The volumes `docker-compose` created for the project. Their sizes are
+/// left for callers that need them to look up with `volume_size`, since
+/// measuring them means starting a container per volume.
+pub fn project_volumes(project: &str) -> Result<Vec<Resource>> {
+    let volumes = lines(
+        Command::new("docker")
+            .args(["volume", "ls", "-q", "--filter"])
+            .arg(project_filter(project)),
+    )?;
+
+    Ok(volumes.iter().map(|name| Resource::volume(name)).collect())
+}
+
+pub fn volume_exists(name: &str) -> Result<bool> {
+    let status = Command::new("docker")
+        .args(["volume", "inspect", name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+
+    Ok(status.success())
+}
+
+/// The size of a volume's contents in bytes, or `None` if it doesn't exist.
+pub fn volume_size(name: &str) -> Result<Option<u64>> {
+    if !volume_exists(name)? {
+        return Ok(None);
+    }
+
+    let output = Command::new("docker")
+        .args(["run", "--rm", "-v"])
+        .arg(format!("{}:/volume:ro", name))
+        .args([HELPER_IMAGE, "du", "-sk", "/volume"])
+        .output()?;
+
+    let kilobytes = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .and_then(|size| size.parse::<u64>().ok());
+
+    Ok(kilobytes.map(|size| size * 1024))
+}
+
+/// The running containers that mount a volume.
+pub fn containers_using(volume: &str) -> Result<Vec<String>> {
+    lines(
+        Command::new("docker")
+            .args(["ps", "-q", "--filter"])
+            .arg(format!("volume={}", volume)),
+    )
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Parses the sizes docker prints, such as `142MB` or `5.6kB`. Docker uses
+/// decimal units.
+fn parse_size(size: &str) -> Option<u64> {
+    let split = size.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier = match unit.to_uppercase().as_str() {
+        "B" => 1e0,
+        "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        _ => return None,
+    };
+
+    Some((number * multiplier) as u64)
+}
+
+fn project_filter(project: &str) -> String {
+    format!("label={}={}", PROJECT_LABEL, project)
+}
+
+/// Runs a docker command, failing with its stderr if it doesn't succeed.
+pub(crate) fn run(cmd: &mut Command, action: &str) -> Result<()> {
+    let output = cmd.stdout(Stdio::null()).output()?;
+
+    if !output.status.success() {
+        Err(format_err!(
+            "Unable to {}: {}",
+            action,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))?
+    }
+
+    Ok(())
+}
+
+/// Runs a docker command and returns the non-empty lines of its output.
+fn lines(cmd: &mut Command) -> Result<Vec<String>> {
+    let output = cmd.output()?;
+
+    if !output.status.success() {
+        Err(format_err!(
+            "docker failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))?
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use failure::ResultExt;
+use toml;
+
+use errors::*;
+
+/// The environment declared in a devbox config file:
+///
+/// ```toml
+/// env_files = ["common.env"]
+///
+/// [env]
+/// DATABASE_URL = "postgres://postgres@postgres/app"
+/// ```
+///
+/// A project's `config.toml` and each service's `.devbox/config.toml` can
+/// declare one. They're layered, each overriding the ones before it:
+///
+/// 1. the project's `env_files`, in order
+/// 2. the project's `[env]`
+/// 3. the service's `env_files`, in order
+/// 4. the service's `[env]`
+/// 5. the task's `env`
+///
+/// `env_files` are relative to the directory of the config file declaring
+/// them.
+#[derive(Clone, Debug, Default)]
+pub struct EnvConfig {
+    pub vars: BTreeMap<String, String>,
+    pub files: Vec<PathBuf>,
+}
+
+impl EnvConfig {
+    /// Reads the `env` table and `env_files` array of a config file.
+    pub fn parse(values: &toml::Value) -> Result<Self> {
+        let mut vars = BTreeMap::new();
+
+        if let Some(env) = values.get("env") {
+            let table = env
+                .as_table()
+                .ok_or_else(|| format_err!("`env` must be a table"))?;
+
+            for (key, value) in table {
+                let value = match *value {
+                    toml::Value::String(ref value) => value.clone(),
+                    toml::Value::Integer(value) => value.to_string(),
+                    toml::Value::Float(value) => value.to_string(),
+                    toml::Value::Boolean(value) => value.to_string(),
+                    _ => Err(format_err!(
+                        "The value of env var `{}` must be a string",
+                        key
+                    ))?,
+                };
+
+                vars.insert(key.clone(), value);
+            }
+        }
+
+        let files = match values.get("env_files") {
+            Some(files) => files.clone().try_into::<Vec<PathBuf>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(EnvConfig { vars, files })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty() && self.files.is_empty()
+    }
+
+    /// Layers this config over `env`: first its files, then its table.
+    pub fn apply(&self, base_dir: &Path, env: &mut BTreeMap<String, String>) -> Result<()> {
+        for file in &self.files {
+            env.extend(read_env_file(&base_dir.join(file))?);
+        }
+
+        env.extend(self.vars.clone());
+
+        Ok(())
+    }
+}
+
+/// Reads a `KEY=value` file as understood by docker-compose: blank lines and
+/// `#` comments are skipped, an `export ` prefix is allowed, and values may be
+/// quoted.
+pub fn read_env_file(path: &Path) -> Result<BTreeMap<String, String>> {
+    let file =
+        File::open(path).with_context(|_| format!("Unable to read env file {}", path.display()))?;
+    let mut env = BTreeMap::new();
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => Err(format_err!("Invalid line in {}: {}", path.display(), line))?,
+        };
+
+        env.insert(key.to_owned(), unquote(value));
+    }
+
+    Ok(env)
+}
+
+fn unquote(value: &str) -> String {
+    let quoted = value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')));
+
+    if !quoted {
+        return value.to_owned();
+    }
+
+    let inner = &value[1..value.len() - 1];
+
+    if value.starts_with('"') {
+        inner
+            .replace("\\n", "\n")
+            .replace("\\\"", "\"")
+            .replace("\\\\", "\\")
+    } else {
+        inner.to_owned()
+    }
+}
+
+/// Writes `env` as a `.env` file, quoting values docker-compose would
+/// otherwise misread. The file is only rewritten when its contents change.
+pub fn write_env_file(path: &Path, env: &BTreeMap<String, String>) -> Result<()> {
+    let mut contents =
+        String::from("# Generated by devbox from config.toml; changes will be overwritten\n");
+
+    for (key, value) in env {
+        let needs_quotes = value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '#' || c == '"' || c == '\'' || c == '\\');
+
+        if needs_quotes {
+            let escaped = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            contents.push_str(&format!("{}=\"{}\"\n", key, escaped));
+        } else {
+            contents.push_str(&format!("{}={}\n", key, value));
+        }
+    }
+
+    if fs::read_to_string(path).ok().as_deref() != Some(contents.as_str()) {
+        fs::write(path, contents)?;
+    }
+
+    Ok(())
+}
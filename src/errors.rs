@@ -1,3 +1,5 @@
+use std::fmt;
+
 use failure;
 
 pub type Error = failure::Error;
@@ -7,6 +9,86 @@ pub type Result<T> = ::std::result::Result<T, Error>;
 #[fail(display = "Unable to find service {}", _0)]
 pub struct ServiceNotFound(pub String);
 
+#[derive(Fail, Debug)]
+#[fail(display = "Network {} is not one of the project's networks", _0)]
+pub struct NetworkNotFound(pub String);
+
 #[derive(Fail, Debug)]
 #[fail(display = "Unimplemented subcommand '{}'; please file a bug", _0)]
 pub struct UnimplementedSubcommand(pub String);
+
+#[derive(Fail, Debug)]
+pub struct TaskNotFound {
+    pub task: String,
+    pub service: String,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for TaskNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Task '{}' could not be found for service {}",
+            self.task, self.service
+        )?;
+
+        match self.suggestion {
+            Some(ref suggestion) => write!(f, "; did you mean `{}`?", suggestion),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Fail, Debug)]
+#[fail(display = "Task '{}' failed for service {} with exit code {}", task, service, code)]
+pub struct TaskFailed {
+    pub service: String,
+    pub task: String,
+    pub code: i32,
+}
+
+#[derive(Fail, Debug)]
+#[fail(display = "The {} hook failed for {}: {}", hook, owner, reason)]
+pub struct HookFailed {
+    pub owner: String,
+    pub hook: String,
+    pub reason: String,
+}
+
+#[derive(Fail, Debug)]
+#[fail(display = "Service {} did not become healthy: {}", _0, _1)]
+pub struct ServiceUnhealthy(pub String, pub String);
+
+#[derive(Fail, Debug)]
+#[fail(display = "Volume {} is not one of the project's volumes", _0)]
+pub struct VolumeNotFound(pub String);
+
+#[derive(Fail, Debug)]
+pub struct SnapshotNotFound {
+    pub volume: String,
+    pub tag: Option<String>,
+}
+
+impl fmt::Display for SnapshotNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.tag {
+            Some(ref tag) => write!(f, "No snapshot '{}' found for volume {}", tag, self.volume),
+            None => write!(f, "No snapshots found for volume {}", self.volume),
+        }
+    }
+}
+
+#[derive(Fail, Debug)]
+#[fail(
+    display = "{} published port(s) are already in use; free them or start with `--remap`",
+    _0
+)]
+pub struct PortsInUse(pub usize);
+
+#[derive(Fail, Debug)]
+#[fail(display = "Profile {} is not one of the project's profiles", _0)]
+pub struct ProfileNotFound(pub String);
+
+#[derive(Fail, Debug)]
+#[fail(display = "Unable to find template {}; see `devbox template list`", _0)]
+pub struct TemplateNotFound(pub String);
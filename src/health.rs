@@ -0,0 +1,70 @@
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use errors::*;
+
+const HEALTH_CHECK_TIMEOUT: u64 = 120;
+
+/// Returns the ids of the containers listed by a `docker-compose ps -q` command.
+pub fn compose_containers(cmd: &mut Command) -> Result<Vec<String>> {
+    let output = cmd.output()?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+/// Polls each container until its health check passes, or until it is
+/// running when the image doesn't define a health check. One-shot containers,
+/// such as migrations, are done once they exit with code 0.
+pub fn wait_until_healthy(owner: &str, containers: &[String]) -> Result<()> {
+    let started = Instant::now();
+
+    if containers.is_empty() {
+        Err(ServiceUnhealthy(
+            owner.to_owned(),
+            "no container is running".into(),
+        ))?
+    }
+
+    for container in containers {
+        loop {
+            let output = Command::new("docker")
+                .arg("inspect")
+                .arg("--format")
+                .arg("{{if .State.Health}}{{.State.Health.Status}}{{else}}{{.State.Status}}{{end}} {{.State.ExitCode}}")
+                .arg(container)
+                .output()?;
+
+            let state = String::from_utf8_lossy(&output.stdout);
+            let mut state = state.split_whitespace();
+
+            match (state.next().unwrap_or_default(), state.next()) {
+                ("healthy", _) | ("running", _) | ("exited", Some("0")) => break,
+                ("starting", _) | ("created", _) | ("restarting", _) => (),
+                ("exited", Some(code)) => Err(ServiceUnhealthy(
+                    owner.to_owned(),
+                    format!("container {} exited with code {}", container, code),
+                ))?,
+                (status, _) => Err(ServiceUnhealthy(
+                    owner.to_owned(),
+                    format!("container {} is {}", container, status),
+                ))?,
+            }
+
+            if started.elapsed() > Duration::from_secs(HEALTH_CHECK_TIMEOUT) {
+                Err(ServiceUnhealthy(
+                    owner.to_owned(),
+                    format!("timed out after {}s", HEALTH_CHECK_TIMEOUT),
+                ))?
+            }
+
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::process::ExitStatus;
+
+use colored::*;
+use serde::de::{self, Deserialize, Deserializer};
+use toml;
+
+use errors::*;
+use task::{Task, TaskMode};
+
+/// The lifecycle events a service can attach hooks to.
+pub const LIFECYCLE_EVENTS: &[&str] = &[
+    "after-build",
+    "after-clone",
+    "after-start",
+    "after-update",
+    "before-build",
+    "before-destroy",
+    "before-start",
+    "before-stop",
+    "before-update",
+];
+
+/// What to do when one of a hook's tasks fails.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePolicy {
+    /// Stop running the hook and fail the surrounding command.
+    #[default]
+    Abort,
+    /// Print a warning and carry on with the next task.
+    Warn,
+    /// Carry on silently.
+    Ignore,
+}
+
+/// A resolved hook, ready to run.
+#[derive(Clone, Debug)]
+pub struct Hook<T> {
+    pub tasks: Vec<T>,
+    pub on_failure: FailurePolicy,
+}
+
+/// A hook as written in a service's `.devbox/config.toml` or a project's
+/// `config.toml`, either a plain list of tasks or a table with a failure
+/// policy:
+///
+/// ```toml
+/// [hooks]
+/// after-build = ["deps"]
+/// before-build = { tasks = ["lint"], on_failure = "warn" }
+/// after-start = [{ task = "migrate" }, { exec = ["./bin/seed"], mode = "host" }]
+/// ```
+///
+/// Task names are only resolved when the hook runs, so hooks may refer to
+/// tasks defined anywhere in the file.
+///
+/// Unknown keys are rejected, so a misspelled `on_failure` doesn't quietly
+/// fall back to the default.
+#[derive(Clone, Debug)]
+pub enum HookConfig {
+    Tasks(Vec<HookTask>),
+    Table {
+        tasks: Vec<HookTask>,
+        on_failure: FailurePolicy,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HookTable {
+    tasks: Vec<HookTask>,
+    #[serde(default)]
+    on_failure: FailurePolicy,
+}
+
+// The variant is picked by the shape of the value rather than with
+// `#[serde(untagged)]`, which only reports that no variant matched and hides
+// which key was wrong.
+impl<'de> Deserialize<'de> for HookConfig {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match toml::Value::deserialize(deserializer)? {
+            value @ toml::Value::Table(_) => value.try_into().map(|table: HookTable| {
+                HookConfig::Table {
+                    tasks: table.tasks,
+                    on_failure: table.on_failure,
+                }
+            }),
+            value => value.try_into().map(HookConfig::Tasks),
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+impl HookConfig {
+    pub fn tasks(&self) -> &[HookTask] {
+        match *self {
+            HookConfig::Tasks(ref tasks) | HookConfig::Table { ref tasks, .. } => tasks,
+        }
+    }
+
+    pub fn on_failure(&self) -> FailurePolicy {
+        match *self {
+            HookConfig::Tasks(_) => FailurePolicy::default(),
+            HookConfig::Table { on_failure, .. } => on_failure,
+        }
+    }
+}
+
+/// A single entry in a hook: the name of a task, or a task defined inline.
+///
+/// TOML arrays can't mix strings and tables, so a named task can also be
+/// written as `{ task = "name" }` next to inline tasks. Project hooks name
+/// tasks as `service:task`.
+#[derive(Clone, Debug)]
+pub enum HookTask {
+    Named(String),
+    Reference { task: String },
+    Inline(InlineTask),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TaskReference {
+    task: String,
+}
+
+impl<'de> Deserialize<'de> for HookTask {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match toml::Value::deserialize(deserializer)? {
+            toml::Value::String(name) => Ok(HookTask::Named(name)),
+            value => match value.get("task") {
+                Some(_) => value
+                    .try_into()
+                    .map(|reference: TaskReference| HookTask::Reference { task: reference.task }),
+                None => value.try_into().map(HookTask::Inline),
+            },
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+impl HookTask {
+    /// The name of the task this entry refers to, if it isn't inline.
+    pub fn task_name(&self) -> Option<&str> {
+        match *self {
+            HookTask::Named(ref name) | HookTask::Reference { task: ref name } => Some(name),
+            HookTask::Inline(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InlineTask {
+    pub exec: Vec<String>,
+    pub mode: Option<TaskMode>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// The service an inline project hook runs in; not needed in host mode.
+    pub service: Option<String>,
+}
+
+impl InlineTask {
+    pub fn to_task(&self) -> Task {
+        Task {
+            name: self.exec.join(" "),
+            description: String::new(),
+            exec: self.exec.clone(),
+            mode: self.mode,
+            env: self.env.clone(),
+        }
+    }
+}
+
+/// Parses the `hooks` table of a devbox config file, warning about any hook
+/// that isn't a known lifecycle event.
+pub fn parse_hooks(values: &toml::Value) -> Result<BTreeMap<String, HookConfig>> {
+    let hooks = match values.get("hooks") {
+        Some(v) => v.clone().try_into::<BTreeMap<String, HookConfig>>()?,
+        None => BTreeMap::new(),
+    };
+
+    for key in hooks.keys() {
+        if !LIFECYCLE_EVENTS.contains(&key.as_str()) {
+            println!(
+                "{} Hook `{}` is not a known lifecycle event",
+                "WARN".yellow(),
+                key
+            );
+        }
+    }
+
+    Ok(hooks)
+}
+
+/// Applies `on_failure` to the outcome of a hook task run on behalf of `owner`.
+pub fn check_task(
+    owner: &str,
+    lifecycle: &str,
+    on_failure: FailurePolicy,
+    task: &Task,
+    outcome: Result<ExitStatus>,
+) -> Result<()> {
+    let reason = match outcome {
+        Ok(ref status) if status.success() => return Ok(()),
+        Ok(status) => format!(
+            "task '{}' exited with code {}",
+            task.name,
+            status.code().unwrap_or(1)
+        ),
+        Err(err) => format!("task '{}' failed: {}", task.name, err),
+    };
+
+    match on_failure {
+        FailurePolicy::Abort => Err(HookFailed {
+            owner: owner.to_owned(),
+            hook: lifecycle.to_owned(),
+            reason,
+        })?,
+        FailurePolicy::Warn => println!(
+            "{} The {} hook failed for {}: {}",
+            "WARN".yellow(),
+            lifecycle,
+            owner,
+            reason
+        ),
+        FailurePolicy::Ignore => (),
+    }
+
+    Ok(())
+}
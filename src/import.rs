@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use failure::ResultExt;
+
+use compose::{Compose, ComposeFile};
+use config::quote_key;
+use errors::*;
+use project::devbox_dir;
+use scaffold::quote;
+use service::COMPOSE_PATH;
+use template::TEMPLATES_DIR;
+
+/// A devbox project made from an existing compose setup.
+///
+/// The compose files are merged into the project's `docker-compose.yml`.
+/// Services with a `build:` context become `path` services: their
+/// definitions move to a `.devbox/docker-compose.yml` in the context
+/// directory, unless one is already there, and the project's services stop
+/// depending on them, since devbox starts them separately. External volumes
+/// and networks become the project's `volumes` and `[networks]`, and an
+/// `.env` next to the first file becomes one of its `env_files`. Relative
+/// paths are made absolute, since devbox runs compose from other directories.
+#[derive(Clone, Debug)]
+pub struct Import {
+    pub name: String,
+    /// The merged compose file, without the services that build.
+    pub compose: Compose,
+    /// The source directory of each service that builds, by service name.
+    pub paths: BTreeMap<String, PathBuf>,
+    /// The services that build, by source directory.
+    pub overlays: BTreeMap<PathBuf, Compose>,
+    pub volumes: Vec<String>,
+    pub networks: Vec<String>,
+    pub env_files: Vec<PathBuf>,
+    /// The `depends_on` entries dropped from the project's services, as
+    /// pairs of service and dependency.
+    pub dropped_dependencies: Vec<(String, String)>,
+    files: Vec<PathBuf>,
+}
+
+impl Import {
+    pub fn new(name: &str, files: &[PathBuf]) -> Result<Self> {
+        if name == TEMPLATES_DIR {
+            Err(format_err!("`{}` is reserved for project templates", name))?
+        }
+
+        let dir = devbox_dir(name)?;
+        if dir.join("config.toml").exists() {
+            Err(format_err!("Project {} already exists at {}", name, dir.display()))?
+        }
+
+        let mut compose = Compose::default();
+        let mut files_read = Vec::new();
+
+        for file in files {
+            let path = fs::canonicalize(file)
+                .with_context(|_| format!("Unable to find {}", file.display()))?;
+
+            compose.merge(ComposeFile::read(&path)?);
+            files_read.push(path);
+        }
+
+        // Compose resolves the relative paths of every file against the first
+        // file's directory, not the directory of the file they're in.
+        if let Some(dir) = files_read.first().and_then(|file| file.parent()) {
+            for service in compose.services.values_mut() {
+                service.resolve_paths(dir);
+            }
+        }
+
+        let mut import = Import {
+            name: name.to_owned(),
+            volumes: compose.external_volumes(),
+            networks: compose.external_networks(),
+            compose,
+            paths: BTreeMap::new(),
+            overlays: BTreeMap::new(),
+            env_files: Vec::new(),
+            dropped_dependencies: Vec::new(),
+            files: files_read,
+        };
+
+        let env_file = import
+            .files
+            .first()
+            .and_then(|file| file.parent())
+            .map(|dir| dir.join(".env"));
+
+        if let Some(env_file) = env_file {
+            if env_file.exists() {
+                import.env_files.push(env_file);
+            }
+        }
+
+        import.extract_services();
+
+        Ok(import)
+    }
+
+    /// Moves the services that build out of the project's compose file, and
+    /// drops the project's dependencies on them so the file stands alone.
+    fn extract_services(&mut self) {
+        let building: Vec<(String, PathBuf)> = self
+            .compose
+            .services
+            .iter()
+            .filter_map(|(name, service)| {
+                let context = service.build_context()?;
+                Some((name.clone(), PathBuf::from(context)))
+            })
+            .collect();
+
+        for (name, context) in building {
+            let service = match self.compose.services.remove(&name) {
+                Some(service) => service,
+                None => continue,
+            };
+
+            self.paths.insert(name.clone(), context.clone());
+
+            let version = self.compose.version.clone();
+            self.overlays
+                .entry(context)
+                .or_insert_with(|| Compose {
+                    version,
+                    ..Compose::default()
+                })
+                .services
+                .insert(name, service);
+        }
+
+        let paths = &self.paths;
+
+        for (name, service) in self.compose.services.iter_mut() {
+            let moved: Vec<String> = service
+                .depends_on
+                .keys()
+                .filter(|dependency| paths.contains_key(*dependency))
+                .cloned()
+                .collect();
+
+            for dependency in moved {
+                service.depends_on.remove(&dependency);
+                self.dropped_dependencies.push((name.clone(), dependency));
+            }
+        }
+    }
+
+    /// The service overlays `write` would create in source directories,
+    /// leaving out the ones that already exist.
+    pub fn new_overlays(&self) -> Vec<PathBuf> {
+        self.overlays
+            .keys()
+            .map(|context| context.join(COMPOSE_PATH))
+            .filter(|path| !path.exists())
+            .collect()
+    }
+
+    /// Writes the project's files and the service overlays, returning the
+    /// files written. Overlays that already exist are left alone.
+    pub fn write(&self) -> Result<Vec<PathBuf>> {
+        let dir = devbox_dir(&self.name)?;
+        let toml_path = dir.join("config.toml");
+
+        if toml_path.exists() {
+            Err(format_err!("Project {} already exists at {}", self.name, dir.display()))?
+        }
+
+        let mut files = vec![
+            (toml_path, self.config_toml()),
+            (dir.join("docker-compose.yml"), self.compose_yaml(&self.compose)?),
+        ];
+
+        for (context, overlay) in &self.overlays {
+            let path = context.join(COMPOSE_PATH);
+
+            if path.exists() {
+                println!("Leaving {} alone, it already exists", path.display());
+                continue;
+            }
+
+            files.push((path, self.compose_yaml(overlay)?));
+        }
+
+        let mut written = Vec::new();
+
+        for (path, contents) in files {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, contents)?;
+            written.push(path);
+        }
+
+        Ok(written)
+    }
+
+    pub fn config_toml(&self) -> String {
+        let mut toml = format!("# Imported by devbox import from {}\n", self.sources());
+
+        if !self.env_files.is_empty() {
+            toml.push_str(&format!("env_files = {}\n", list(&self.env_files)));
+        }
+
+        if !self.volumes.is_empty() {
+            let volumes: Vec<String> = self.volumes.iter().map(|volume| quote(volume)).collect();
+            toml.push_str(&format!("volumes = [{}]\n", volumes.join(", ")));
+        }
+
+        for network in &self.networks {
+            toml.push_str(&format!("\n[networks.{}]\n", quote_key(network)));
+        }
+
+        toml.push_str("\n[services]\n");
+        for (name, path) in &self.paths {
+            toml.push_str(&format!(
+                "{} = {{ path = {} }}\n",
+                quote_key(name),
+                quote(&path.to_string_lossy())
+            ));
+        }
+
+        toml
+    }
+
+    fn compose_yaml(&self, compose: &Compose) -> Result<String> {
+        Ok(format!(
+            "# Imported by devbox import from {}\n{}",
+            self.sources(),
+            compose.to_yaml()?
+        ))
+    }
+
+    fn sources(&self) -> String {
+        let sources: Vec<String> = self
+            .files
+            .iter()
+            .map(|file| file.display().to_string())
+            .collect();
+        sources.join(", ")
+    }
+}
+
+fn list(paths: &[PathBuf]) -> String {
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|path| quote(&path.to_string_lossy()))
+        .collect();
+    format!("[{}]", paths.join(", "))
+}
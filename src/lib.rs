@@ -1,5 +1,6 @@
 #![recursion_limit = "1024"]
 
+extern crate chrono;
 extern crate colored;
 extern crate dirs;
 #[macro_use]
@@ -8,17 +9,52 @@ extern crate failure;
 extern crate failure_derive;
 #[macro_use]
 extern crate prettytable;
+extern crate regex;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
 extern crate tempdir;
 extern crate toml;
+extern crate toml_edit;
 
+mod compose;
+mod config;
+pub mod docker;
+mod environment;
 mod errors;
+mod health;
+mod hook;
+mod import;
+mod logs;
+mod network;
+mod output;
+mod ports;
+mod profile;
 mod project;
+mod scaffold;
+mod secrets;
 mod service;
 mod task;
+mod template;
+mod volume;
 
+pub use compose::*;
+pub use config::*;
+pub use environment::*;
 pub use errors::*;
+pub use hook::*;
+pub use import::*;
+pub use logs::*;
+pub use network::*;
+pub use output::*;
+pub use ports::*;
+pub use profile::*;
 pub use project::*;
+pub use scaffold::*;
+pub use secrets::*;
 pub use service::*;
+pub use task::*;
+pub use template::*;
+pub use volume::*;
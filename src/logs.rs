@@ -0,0 +1,478 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::Local;
+use regex::Regex;
+use serde_json;
+
+use errors::*;
+use health;
+use output::Prefix;
+use project;
+
+/// The severity of a structured log line, lowest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl FromStr for Level {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "trace" => Ok(Level::Trace),
+            "debug" => Ok(Level::Debug),
+            "info" | "notice" => Ok(Level::Info),
+            "warn" | "warning" => Ok(Level::Warn),
+            "error" | "err" => Ok(Level::Error),
+            "fatal" | "critical" | "crit" | "panic" => Ok(Level::Fatal),
+            _ => Err(format_err!("Unknown log level '{}'", s)),
+        }
+    }
+}
+
+impl Level {
+    /// Reads the level of a JSON log line from its `level`, `lvl`, or
+    /// `severity` field, understanding bunyan/pino style numeric levels.
+    fn of_json_line(line: &str) -> Option<Level> {
+        let value = serde_json::from_str::<serde_json::Value>(line).ok()?;
+        let level = ["level", "lvl", "severity"]
+            .iter()
+            .filter_map(|key| value.get(*key))
+            .next()?;
+
+        match *level {
+            serde_json::Value::String(ref level) => level.parse().ok(),
+            serde_json::Value::Number(ref level) => match level.as_u64()? {
+                0..=10 => Some(Level::Trace),
+                11..=20 => Some(Level::Debug),
+                21..=30 => Some(Level::Info),
+                31..=40 => Some(Level::Warn),
+                41..=50 => Some(Level::Error),
+                _ => Some(Level::Fatal),
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LogOptions {
+    pub follow: bool,
+    pub tail: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub timestamps: bool,
+    /// Only show lines matching this pattern.
+    pub grep: Option<Regex>,
+    /// Only show JSON lines logged at this level or above.
+    pub level: Option<Level>,
+    /// Also write every line, unfiltered, to the project's log archive.
+    pub record: Option<LogArchive>,
+}
+
+impl LogOptions {
+    fn matches(&self, line: &str) -> bool {
+        if let Some(ref grep) = self.grep {
+            if !grep.is_match(line) {
+                return false;
+            }
+        }
+
+        if let Some(level) = self.level {
+            // With timestamps docker puts one in front of the JSON document.
+            let body = match line.split_once(' ') {
+                Some((_, body)) if self.timestamps => body,
+                _ => line,
+            };
+
+            return Level::of_json_line(body).is_some_and(|line_level| line_level >= level);
+        }
+
+        true
+    }
+}
+
+/// A container whose logs belong to a service.
+#[derive(Clone, Debug)]
+pub struct LogSource {
+    pub service: String,
+    pub container: String,
+}
+
+impl LogSource {
+    /// Finds the containers of each service using `compose`, which builds a
+    /// `docker-compose` command with the right compose files. Services without
+    /// a running container are skipped with a note.
+    pub fn discover<F>(compose: F, services: &[String]) -> Result<Vec<LogSource>>
+    where
+        F: Fn() -> Result<Command>,
+    {
+        let mut sources = Vec::new();
+
+        for service in services {
+            let containers = health::compose_containers(compose()?.arg("ps").arg("-q").arg(service))?;
+
+            if containers.is_empty() {
+                eprintln!("{} has no running container, skipping", service);
+                continue;
+            }
+
+            sources.extend(containers.into_iter().map(|container| LogSource {
+                service: service.clone(),
+                container,
+            }));
+        }
+
+        Ok(sources)
+    }
+}
+
+/// Streams the logs of every source concurrently, each line prefixed and
+/// colored by its service, until all of them finish.
+pub fn stream(sources: &[LogSource], options: &LogOptions) -> Result<()> {
+    let width = sources
+        .iter()
+        .map(|source| source.service.len())
+        .max()
+        .unwrap_or(0);
+    let options = Arc::new(options.clone());
+    let mut recorders = BTreeMap::new();
+    let mut handles = Vec::new();
+
+    if let Some(ref archive) = options.record {
+        for source in sources {
+            if !recorders.contains_key(&source.service) {
+                let recorder = archive.recorder(&source.service)?;
+                recorders.insert(source.service.clone(), Arc::new(Mutex::new(recorder)));
+            }
+        }
+    }
+
+    for source in sources {
+        // A recorded stream picks up where the last recording stopped, so the
+        // lines already in the archive aren't appended to it again.
+        let since = match recorders.get(&source.service) {
+            Some(recorder) => recorder.lock().ok().and_then(|recorder| recorder.since.clone()),
+            None => None,
+        };
+        let mut child = docker_logs(&source.container, &options, since.as_ref().or(options.since.as_ref()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let prefix = Prefix::new(&source.service, width);
+        let recorder = recorders.get(&source.service).cloned();
+
+        if let Some(stdout) = child.stdout.take() {
+            handles.push(forward(stdout, prefix.clone(), false, options.clone(), recorder.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            handles.push(forward(stderr, prefix, true, options.clone(), recorder));
+        }
+
+        handles.push(thread::spawn(move || {
+            let _ = child.wait();
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    Ok(())
+}
+
+fn docker_logs(container: &str, options: &LogOptions, since: Option<&String>) -> Command {
+    let mut cmd = Command::new("docker");
+    cmd.arg("logs");
+
+    if options.follow {
+        cmd.arg("--follow");
+    }
+    if options.timestamps {
+        cmd.arg("--timestamps");
+    }
+    if let Some(ref tail) = options.tail {
+        cmd.arg("--tail").arg(tail);
+    }
+    if let Some(since) = since {
+        cmd.arg("--since").arg(since);
+    }
+    if let Some(ref until) = options.until {
+        cmd.arg("--until").arg(until);
+    }
+
+    cmd.arg(container);
+    cmd
+}
+
+fn forward<R: Read + Send + 'static>(
+    reader: R,
+    prefix: Prefix,
+    stderr: bool,
+    options: Arc<LogOptions>,
+    recorder: Option<Arc<Mutex<LogRecorder>>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            if let Some(ref recorder) = recorder {
+                if let Ok(mut recorder) = recorder.lock() {
+                    if let Err(err) = recorder.write_line(&line) {
+                        eprintln!("{}", prefix.line(&format!("unable to record logs: {}", err)));
+                    }
+                }
+            }
+
+            if !options.matches(&line) {
+                continue;
+            }
+
+            if stderr {
+                eprintln!("{}", prefix.line(&line));
+            } else {
+                println!("{}", prefix.line(&line));
+            }
+        }
+    })
+}
+
+/// The `[logs]` table of a project's `config.toml`:
+///
+/// ```toml
+/// [logs]
+/// record = true        # record every `devbox logs` stream
+/// max_size_mb = 10     # rotate a day's log file once it reaches this size
+/// retention_days = 7   # delete recorded logs older than this
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct LogSettings {
+    #[serde(default)]
+    pub record: bool,
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64,
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u64,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        LogSettings {
+            record: false,
+            max_size_mb: default_max_size_mb(),
+            retention_days: default_retention_days(),
+        }
+    }
+}
+
+fn default_max_size_mb() -> u64 {
+    10
+}
+
+fn default_retention_days() -> u64 {
+    7
+}
+
+/// Recorded service logs, kept in
+/// `~/.config/devbox/<project>/logs/<service>/<date>.log`.
+///
+/// Once a day's file reaches `max_size_mb` it is renamed to
+/// `<date>.<n>.log` and a new `<date>.log` is started.
+#[derive(Clone, Debug)]
+pub struct LogArchive {
+    dir: PathBuf,
+    settings: LogSettings,
+}
+
+impl LogArchive {
+    pub fn new(project_name: &str, settings: &LogSettings) -> Result<Self> {
+        Ok(LogArchive {
+            dir: project::devbox_dir(project_name)?.join("logs"),
+            settings: settings.clone(),
+        })
+    }
+
+    pub fn recorder(&self, service: &str) -> Result<LogRecorder> {
+        let dir = self.dir.join(service);
+
+        fs::create_dir_all(&dir)?;
+        self.prune(&dir)?;
+
+        Ok(LogRecorder {
+            since: last_recorded(&dir)?,
+            dir,
+            max_size: self.settings.max_size_mb * 1024 * 1024,
+            current: None,
+        })
+    }
+
+    /// The recorded log files for a service, oldest first.
+    pub fn history(&self, service: &str) -> Result<Vec<PathBuf>> {
+        let dir = self.dir.join(service);
+
+        if !dir.exists() {
+            Err(format_err!("No recorded logs found for {}", service))?
+        }
+
+        let mut files: Vec<(String, u64, PathBuf)> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter_map(|path| {
+                let (date, segment) = segment_of(&path)?;
+                Some((date, segment, path))
+            })
+            .collect();
+
+        files.sort();
+
+        Ok(files.into_iter().map(|(_, _, path)| path).collect())
+    }
+
+    /// Prints a service's recorded logs, applying the filters in `options`.
+    pub fn print_history(&self, service: &str, options: &LogOptions) -> Result<()> {
+        let prefix = Prefix::new(service, service.len());
+
+        for path in self.history(service)? {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let line = line?;
+
+                if options.matches(&line) {
+                    println!("{}", prefix.line(&line));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn prune(&self, dir: &Path) -> Result<()> {
+        let retention = Duration::from_secs(self.settings.retention_days * 24 * 60 * 60);
+        let now = SystemTime::now();
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let modified = fs::metadata(&path)?.modified()?;
+
+            if segment_of(&path).is_some()
+                && now.duration_since(modified).unwrap_or_default() > retention
+            {
+                fs::remove_file(&path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Appends a service's log lines to the archive, rotating by size and date.
+#[derive(Debug)]
+pub struct LogRecorder {
+    dir: PathBuf,
+    max_size: u64,
+    current: Option<CurrentLog>,
+    /// When the service's logs were last recorded, as a `docker logs --since`
+    /// timestamp.
+    since: Option<String>,
+}
+
+/// The day's log file being appended to and its size so far.
+#[derive(Debug)]
+struct CurrentLog {
+    date: String,
+    file: File,
+    size: u64,
+}
+
+impl LogRecorder {
+    pub fn write_line(&mut self, line: &str) -> Result<()> {
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        let path = self.dir.join(format!("{}.log", today));
+
+        let is_current = match self.current {
+            Some(ref current) => current.date == today,
+            None => false,
+        };
+
+        if !is_current {
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let size = file.metadata()?.len();
+
+            self.current = Some(CurrentLog { date: today.clone(), file, size });
+        }
+
+        if self.current.as_ref().is_some_and(|current| current.size >= self.max_size) {
+            self.current = None;
+            self.rotate(&today, &path)?;
+
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            self.current = Some(CurrentLog { date: today, file, size: 0 });
+        }
+
+        if let Some(ref mut current) = self.current {
+            writeln!(current.file, "{}", line)?;
+            current.size += line.len() as u64 + 1;
+        }
+
+        Ok(())
+    }
+
+    fn rotate(&self, date: &str, path: &Path) -> Result<()> {
+        let mut segment = 1;
+
+        while self.dir.join(format!("{}.{}.log", date, segment)).exists() {
+            segment += 1;
+        }
+
+        fs::rename(path, self.dir.join(format!("{}.{}.log", date, segment)))?;
+
+        Ok(())
+    }
+}
+
+/// The time the last line was recorded in `dir`, from the newest file's
+/// modification time.
+fn last_recorded(dir: &Path) -> Result<Option<String>> {
+    let mut last = None;
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if segment_of(&path).is_some() {
+            let modified = fs::metadata(&path)?.modified()?;
+            last = last.max(Some(modified));
+        }
+    }
+
+    Ok(last
+        .and_then(|last| last.duration_since(UNIX_EPOCH).ok())
+        .map(|last| format!("{}.{:09}", last.as_secs(), last.subsec_nanos())))
+}
+
+/// Splits a recorded log file name into its date and segment number, with the
+/// file still being written to sorting after the rotated ones.
+fn segment_of(path: &Path) -> Option<(String, u64)> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name.strip_suffix(".log")?;
+
+    match stem.split_once('.') {
+        Some((date, segment)) => Some((date.to_owned(), segment.parse().ok()?)),
+        None => Some((stem.to_owned(), u64::MAX)),
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use colored::*;
+use prettytable::format;
+use prettytable::Table;
+use serde_json;
+use toml;
+
+use docker;
+use errors::*;
+
+/// The compose file devbox generates, next to the project's
+/// `docker-compose.yml`, to give services their network aliases.
+const OVERRIDES_FILE: &str = "docker-compose.networks.yml";
+
+/// A network as declared in the `[networks]` table of a project's
+/// `config.toml`:
+///
+/// ```toml
+/// [networks.example]
+/// driver = "bridge"
+/// subnet = "172.28.0.0/16"
+/// aliases = { postgres = ["db", "postgres.local"] }
+/// ```
+///
+/// `aliases` maps a compose service to the extra names it is reachable by on
+/// this network.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct NetworkConfig {
+    pub driver: Option<String>,
+    pub subnet: Option<String>,
+    #[serde(default)]
+    pub aliases: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Network {
+    pub name: String,
+    pub config: NetworkConfig,
+}
+
+/// What docker knows about an existing network.
+#[derive(Clone, Debug)]
+struct NetworkState {
+    driver: String,
+    subnets: Vec<String>,
+    containers: usize,
+}
+
+impl Network {
+    /// Creates the network unless it already exists, returning whether it was
+    /// created. An existing network whose driver or subnet differ from the
+    /// configuration is left alone with a warning, since changing it means
+    /// removing it first.
+    pub fn create(&self) -> Result<bool> {
+        if let Some(state) = self.state()? {
+            self.check_state(&state);
+            return Ok(false);
+        }
+
+        let mut cmd = Command::new("docker");
+        cmd.args(["network", "create"]);
+
+        if let Some(ref driver) = self.config.driver {
+            cmd.arg("--driver").arg(driver);
+        }
+        if let Some(ref subnet) = self.config.subnet {
+            cmd.arg("--subnet").arg(subnet);
+        }
+
+        cmd.arg(&self.name);
+
+        docker::run(&mut cmd, &format!("create network {}", self.name))?;
+
+        Ok(true)
+    }
+
+    /// Removes the network, returning whether there was one to remove.
+    pub fn remove(&self) -> Result<bool> {
+        if self.state()?.is_none() {
+            return Ok(false);
+        }
+
+        docker::run(
+            Command::new("docker").args(["network", "rm", &self.name]),
+            &format!("remove network {}", self.name),
+        )?;
+
+        Ok(true)
+    }
+
+    fn check_state(&self, state: &NetworkState) {
+        if let Some(ref driver) = self.config.driver {
+            if *driver != state.driver {
+                println!(
+                    "{} Network {} uses the {} driver rather than {}; remove it with `devbox network rm {}` to recreate it",
+                    "WARN".yellow(),
+                    self.name,
+                    state.driver,
+                    driver,
+                    self.name
+                );
+            }
+        }
+
+        if let Some(ref subnet) = self.config.subnet {
+            if !state.subnets.contains(subnet) {
+                println!(
+                    "{} Network {} has subnet {} rather than {}; remove it with `devbox network rm {}` to recreate it",
+                    "WARN".yellow(),
+                    self.name,
+                    state.subnets.join(", "),
+                    subnet,
+                    self.name
+                );
+            }
+        }
+    }
+
+    fn state(&self) -> Result<Option<NetworkState>> {
+        let output = Command::new("docker")
+            .args(["network", "inspect", "--format"])
+            .arg("{{.Driver}}\t{{range .IPAM.Config}}{{.Subnet}} {{end}}\t{{len .Containers}}")
+            .arg(&self.name)
+            .stderr(Stdio::null())
+            .output()?;
+
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut fields = stdout.trim().split('\t');
+
+        Ok(Some(NetworkState {
+            driver: fields.next().unwrap_or_default().to_owned(),
+            subnets: fields
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+            containers: fields
+                .next()
+                .and_then(|count| count.parse().ok())
+                .unwrap_or(0),
+        }))
+    }
+}
+
+/// Parses the `[networks]` table of a project's `config.toml`. Projects
+/// without one get a single network named after the project.
+pub fn parse_networks(values: &toml::Value, project_name: &str) -> Result<Vec<Network>> {
+    let configs = match values.get("networks") {
+        Some(v) => v.clone().try_into::<BTreeMap<String, NetworkConfig>>()?,
+        None => {
+            let mut configs = BTreeMap::new();
+            configs.insert(project_name.to_owned(), NetworkConfig::default());
+            configs
+        }
+    };
+
+    Ok(configs
+        .into_iter()
+        .map(|(name, config)| Network { name, config })
+        .collect())
+}
+
+/// Prints each network with what docker knows about it.
+pub fn print_networks(networks: &[Network]) -> Result<()> {
+    let mut table = Table::new();
+
+    table.set_format(*format::consts::FORMAT_CLEAN);
+    table.add_row(row!["NETWORK", "DRIVER", "SUBNET", "CONTAINERS"]);
+
+    for network in networks {
+        match network.state()? {
+            Some(state) => table.add_row(row![
+                network.name,
+                state.driver,
+                state.subnets.join(", "),
+                state.containers
+            ]),
+            None => table.add_row(row![network.name, "not created", "", ""]),
+        };
+    }
+
+    table.printstd();
+
+    Ok(())
+}
+
+/// The generated alias overrides of the project whose compose file lives in
+/// `project_dir`.
+pub(crate) fn overrides_file(project_dir: &Path) -> PathBuf {
+    project_dir.join(OVERRIDES_FILE)
+}
+
+/// Writes the compose overrides that attach each service's aliases to its
+/// networks, or removes them when no network declares any aliases. The
+/// networks are declared as external, since devbox creates them, unless the
+/// project's compose files in `declared` already do.
+pub fn write_overrides(
+    project_dir: &Path,
+    networks: &[Network],
+    declared: &BTreeSet<String>,
+) -> Result<()> {
+    let path = overrides_file(project_dir);
+
+    match overrides_yaml(networks, declared) {
+        Some(yaml) => {
+            let current = fs::read_to_string(&path).unwrap_or_default();
+            if current != yaml {
+                fs::write(&path, yaml)?;
+            }
+        }
+        None => {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn overrides_yaml(networks: &[Network], declared: &BTreeSet<String>) -> Option<String> {
+    let mut services: BTreeMap<&str, Vec<(&str, &[String])>> = BTreeMap::new();
+    let mut referenced = BTreeSet::new();
+
+    for network in networks {
+        for (service, aliases) in &network.config.aliases {
+            services
+                .entry(service)
+                .or_default()
+                .push((&network.name, aliases));
+            referenced.insert(network.name.as_str());
+        }
+    }
+
+    if services.is_empty() {
+        return None;
+    }
+
+    // JSON strings are valid YAML scalars, which takes care of quoting.
+    let quote = |s: &str| serde_json::to_string(s).unwrap_or_default();
+    let mut yaml = String::from("# Generated by devbox from the [networks] table of config.toml\n");
+    yaml.push_str("version: \"3\"\n\nservices:\n");
+
+    for (service, networks) in services {
+        yaml.push_str(&format!("  {}:\n    networks:\n", quote(service)));
+
+        for (network, aliases) in networks {
+            yaml.push_str(&format!("      {}:\n        aliases:\n", quote(network)));
+
+            for alias in aliases {
+                yaml.push_str(&format!("          - {}\n", quote(alias)));
+            }
+        }
+    }
+
+    // The key is the network's name; `name:` would need version 3.5, which
+    // compose 1 won't merge with the other files' version 3.
+    let undeclared: Vec<&str> = referenced
+        .into_iter()
+        .filter(|network| !declared.contains(*network))
+        .collect();
+
+    if !undeclared.is_empty() {
+        yaml.push_str("\nnetworks:\n");
+
+        for network in undeclared {
+            yaml.push_str(&format!("  {}:\n    external: true\n", quote(network)));
+        }
+    }
+
+    Some(yaml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compose::Compose;
+    use tempdir::TempDir;
+
+    fn network(name: &str, aliases: &[(&str, &[&str])]) -> Network {
+        Network {
+            name: name.to_owned(),
+            config: NetworkConfig {
+                aliases: aliases
+                    .iter()
+                    .map(|&(service, aliases)| {
+                        (service.to_owned(), aliases.iter().map(|a| a.to_string()).collect())
+                    })
+                    .collect(),
+                ..NetworkConfig::default()
+            },
+        }
+    }
+
+    #[test]
+    fn overrides_declare_the_networks_they_attach_services_to() {
+        let networks = vec![
+            network("ex", &[("api", &["api.local"]), ("postgres", &["db"])]),
+            network("backend", &[("postgres", &["db"])]),
+            network("unused", &[]),
+        ];
+        let dir = TempDir::new("devbox").unwrap();
+
+        write_overrides(dir.path(), &networks, &BTreeSet::new()).unwrap();
+
+        let yaml = fs::read_to_string(overrides_file(dir.path())).unwrap();
+        let compose = Compose::parse(&yaml).unwrap();
+
+        assert_eq!(compose.networks.keys().collect::<Vec<_>>(), vec!["backend", "ex"]);
+        assert!(compose.networks.values().all(|network| network.is_external()));
+        assert_eq!(
+            compose.services["postgres"].networks["backend"].aliases,
+            vec!["db"]
+        );
+    }
+
+    #[test]
+    fn overrides_leave_declared_networks_alone() {
+        let networks = vec![
+            network("ex", &[("api", &["api.local"])]),
+            network("backend", &[("postgres", &["db"])]),
+        ];
+        let declared = vec!["ex".to_owned()].into_iter().collect();
+        let compose = Compose::parse(&overrides_yaml(&networks, &declared).unwrap()).unwrap();
+
+        assert_eq!(compose.networks.keys().collect::<Vec<_>>(), vec!["backend"]);
+    }
+
+    #[test]
+    fn overrides_are_removed_without_aliases() {
+        let dir = TempDir::new("devbox").unwrap();
+        let path = overrides_file(dir.path());
+        fs::write(&path, "services: {}\n").unwrap();
+
+        write_overrides(dir.path(), &[network("ex", &[])], &BTreeSet::new()).unwrap();
+
+        assert!(!path.exists());
+    }
+}
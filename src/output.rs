@@ -0,0 +1,76 @@
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+use colored::*;
+
+use errors::*;
+
+const COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::Red,
+];
+
+/// A colorized, padded label written in front of every line of output that
+/// belongs to a service, e.g. `api  | Compiling...`.
+#[derive(Clone, Debug)]
+pub struct Prefix {
+    label: String,
+}
+
+impl Prefix {
+    pub fn new(name: &str, width: usize) -> Self {
+        let label = format!("{:width$} |", name, width = width)
+            .color(color_for(name))
+            .to_string();
+
+        Prefix { label }
+    }
+
+    pub fn line(&self, line: &str) -> String {
+        format!("{} {}", self.label, line)
+    }
+}
+
+/// Picks a stable color for a service so it keeps the same color between runs.
+pub fn color_for(name: &str) -> Color {
+    let hash = name.bytes().fold(0usize, |acc, b| acc.wrapping_add(b as usize));
+    COLORS[hash % COLORS.len()]
+}
+
+/// Spawns `cmd` with its stdout and stderr piped, writing each line back out
+/// with `prefix` in front of it, and waits for it to exit.
+pub fn spawn_prefixed(cmd: &mut Command, prefix: &Prefix) -> Result<ExitStatus> {
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+
+    let stdout = child.stdout.take().map(|out| forward(out, prefix.clone(), false));
+    let stderr = child.stderr.take().map(|err| forward(err, prefix.clone(), true));
+
+    let status = child.wait()?;
+
+    for handle in stdout.into_iter().chain(stderr) {
+        let _ = handle.join();
+    }
+
+    Ok(status)
+}
+
+fn forward<R: Read + Send + 'static>(
+    reader: R,
+    prefix: Prefix,
+    stderr: bool,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            match line {
+                Ok(ref line) if stderr => eprintln!("{}", prefix.line(line)),
+                Ok(ref line) => println!("{}", prefix.line(line)),
+                Err(_) => break,
+            }
+        }
+    })
+}
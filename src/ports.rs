@@ -0,0 +1,616 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{TcpListener, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use colored::*;
+use prettytable::format;
+use prettytable::Table;
+use serde_json;
+use serde_yaml::Value;
+
+use compose::{ComposeFile, ComposeFiles, Port};
+use docker::PROJECT_LABEL;
+use errors::*;
+
+/// The compose file `--remap` writes, next to the project's
+/// `docker-compose.yml`, to move services off ports that are taken.
+const OVERRIDES_FILE: &str = "docker-compose.ports.yml";
+
+/// How far past a taken port `--remap` looks for a free one.
+const REMAP_SEARCH_LIMIT: u16 = 100;
+
+/// The first compose release that understands the `!override` tag the
+/// overrides file uses.
+const OVERRIDE_TAG_VERSION: (u64, u64, u64) = (2, 24, 4);
+
+/// A `ports:` entry that publishes container ports on the host.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PortMapping {
+    pub host_ip: Option<String>,
+    /// The first and last host port; they're the same unless it's a range.
+    pub host_ports: (u16, u16),
+    pub container_ports: String,
+    pub protocol: String,
+}
+
+impl PortMapping {
+    /// Parses the short (`"127.0.0.1:5432:5432/tcp"`) or long syntax of a
+    /// `ports:` entry. Entries that don't publish a fixed host port, or that
+    /// can't be understood without interpolation, are skipped.
+    fn parse(port: &Port) -> Option<PortMapping> {
+        match *port {
+            Port::Number(_) => None,
+            Port::Short(ref spec) => PortMapping::parse_short(spec),
+            Port::Long(ref mapping) => {
+                let value = Value::Mapping(mapping.clone());
+                let published = match value.get("published")? {
+                    Value::Number(ref port) => port.as_u64()?.to_string(),
+                    Value::String(ref port) => port.clone(),
+                    _ => return None,
+                };
+                let target = match value.get("target")? {
+                    Value::Number(ref port) => port.as_u64()?.to_string(),
+                    Value::String(ref port) => port.clone(),
+                    _ => return None,
+                };
+
+                Some(PortMapping {
+                    host_ip: value
+                        .get("host_ip")
+                        .and_then(Value::as_str)
+                        .map(String::from),
+                    host_ports: parse_range(&published)?,
+                    container_ports: target,
+                    protocol: value
+                        .get("protocol")
+                        .and_then(Value::as_str)
+                        .unwrap_or("tcp")
+                        .to_owned(),
+                })
+            }
+        }
+    }
+
+    fn parse_short(spec: &str) -> Option<PortMapping> {
+        let (spec, protocol) = match spec.rsplit_once('/') {
+            Some((spec, protocol)) => (spec, protocol),
+            None => (spec, "tcp"),
+        };
+
+        // IPv6 addresses are written in brackets.
+        let (host_ip, rest) = if spec.starts_with('[') {
+            let end = spec.find("]:")?;
+            (Some(spec[1..end].to_owned()), &spec[end + 2..])
+        } else {
+            (None, spec)
+        };
+
+        let parts: Vec<&str> = rest.split(':').collect();
+        let (host_ip, host, container) = match (host_ip, parts.as_slice()) {
+            (Some(ip), [host, container]) => (Some(ip), *host, *container),
+            (None, [host, container]) => (None, *host, *container),
+            (None, [ip, host, container]) => (Some((*ip).to_owned()), *host, *container),
+            _ => return None,
+        };
+
+        Some(PortMapping {
+            host_ip,
+            host_ports: parse_range(host)?,
+            container_ports: container.to_owned(),
+            protocol: protocol.to_owned(),
+        })
+    }
+
+    fn is_range(&self) -> bool {
+        self.host_ports.0 != self.host_ports.1
+    }
+
+    fn host_ports(&self) -> impl Iterator<Item = u16> {
+        self.host_ports.0..=self.host_ports.1
+    }
+
+    fn to_short_syntax(&self) -> String {
+        let host = if self.is_range() {
+            format!("{}-{}", self.host_ports.0, self.host_ports.1)
+        } else {
+            self.host_ports.0.to_string()
+        };
+
+        match self.host_ip {
+            Some(ref ip) if ip.contains(':') => {
+                format!(
+                    "[{}]:{}:{}/{}",
+                    ip, host, self.container_ports, self.protocol
+                )
+            }
+            Some(ref ip) => format!("{}:{}:{}/{}", ip, host, self.container_ports, self.protocol),
+            None => format!("{}:{}/{}", host, self.container_ports, self.protocol),
+        }
+    }
+}
+
+fn parse_range(ports: &str) -> Option<(u16, u16)> {
+    match ports.split_once('-') {
+        Some((first, last)) => Some((first.parse().ok()?, last.parse().ok()?)),
+        None => {
+            let port = ports.parse().ok()?;
+            Some((port, port))
+        }
+    }
+}
+
+/// A host port a service publishes that something else already holds.
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    pub service: String,
+    pub port: u16,
+    pub protocol: String,
+    /// The container or process holding the port, as far as we can tell.
+    pub owner: String,
+}
+
+/// A published port moved by `--remap`.
+#[derive(Clone, Debug)]
+pub struct Remap {
+    pub service: String,
+    pub from: u16,
+    pub to: u16,
+}
+
+/// Checks the host ports published across a set of compose files before
+/// `project`, whose compose file lives in `project_dir`, is started. Ports held by the project's own containers for the
+/// same service don't count, since `up` reuses those containers.
+///
+/// Only the services `selected` accepts are checked, though the ports of
+/// every service are avoided when remapping.
+///
+/// Conflicts fail with a report unless `remap` is set, in which case the
+/// conflicting ports are moved to free ones in the generated overrides file.
+pub fn check_ports<F>(
+    project: &str,
+    project_dir: &Path,
+    files: &ComposeFiles,
+    selected: F,
+    remap: bool,
+) -> Result<Vec<Remap>>
+where
+    F: Fn(&str) -> bool,
+{
+    let overrides = overrides_file(project_dir);
+
+    // Compose may accept files devbox can't read, which shouldn't keep them
+    // from starting.
+    let ports = match published_ports(files) {
+        Ok(ports) => ports,
+        Err(err) => {
+            println!("{} Unable to check published ports: {}", "WARN".yellow(), err);
+            return Ok(Vec::new());
+        }
+    };
+    let mut conflicts = find_conflicts(project, &ports)?;
+
+    conflicts.retain(|conflict| selected(&conflict.service));
+
+    if conflicts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    if !remap {
+        print_conflicts(&conflicts);
+        Err(PortsInUse(conflicts.len()))?
+    }
+
+    check_override_support()?;
+
+    let remaps = remap_conflicts(&ports, &conflicts)?;
+    write_overrides(&overrides, &ports, &remaps)?;
+
+    for remap in &remaps {
+        println!(
+            "{} Moved {} from port {} to {}",
+            "INFO".green(),
+            remap.service,
+            remap.from,
+            remap.to
+        );
+    }
+
+    Ok(remaps)
+}
+
+/// The generated port overrides of the project whose compose file lives in
+/// `project_dir`.
+pub(crate) fn overrides_file(project_dir: &Path) -> PathBuf {
+    project_dir.join(OVERRIDES_FILE)
+}
+
+/// A service's `ports:` entries as written, along with the ones that publish
+/// fixed host ports.
+#[derive(Clone, Debug, Default)]
+struct ServicePorts {
+    entries: Vec<Port>,
+    mappings: Vec<PortMapping>,
+}
+
+/// The ports each service publishes once all of the files are merged.
+fn published_ports(files: &ComposeFiles) -> Result<BTreeMap<String, ServicePorts>> {
+    Ok(files
+        .load()?
+        .services
+        .into_iter()
+        .filter(|(_, service)| !service.ports.is_empty())
+        .map(|(name, service)| {
+            let mappings = service.ports.iter().filter_map(PortMapping::parse).collect();
+            let ports = ServicePorts {
+                entries: service.ports,
+                mappings,
+            };
+            (name, ports)
+        })
+        .collect())
+}
+
+/// A running container's published port, from `docker ps`.
+#[derive(Clone, Debug)]
+struct ContainerPort {
+    project: String,
+    service: String,
+    container: String,
+    port: u16,
+    protocol: String,
+}
+
+fn find_conflicts(project: &str, ports: &BTreeMap<String, ServicePorts>) -> Result<Vec<Conflict>> {
+    let container_ports = container_ports()?;
+    let mut conflicts = Vec::new();
+
+    for (service, service_ports) in ports {
+        for mapping in &service_ports.mappings {
+            for port in mapping.host_ports() {
+                let holder = container_ports
+                    .iter()
+                    .find(|held| held.port == port && held.protocol == mapping.protocol);
+
+                let owner = match holder {
+                    Some(held) if held.project == project && held.service == *service => continue,
+                    Some(held) => Some(format!("container {}", held.container)),
+                    None if is_free(mapping.host_ip.as_deref(), port, &mapping.protocol) => None,
+                    None => Some(process_holding(port, &mapping.protocol)),
+                };
+
+                if let Some(owner) = owner {
+                    conflicts.push(Conflict {
+                        service: service.clone(),
+                        port,
+                        protocol: mapping.protocol.clone(),
+                        owner,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(conflicts)
+}
+
+fn print_conflicts(conflicts: &[Conflict]) {
+    let mut table = Table::new();
+
+    table.set_format(*format::consts::FORMAT_CLEAN);
+    table.add_row(row!["SERVICE", "PORT", "IN USE BY"]);
+
+    for conflict in conflicts {
+        table.add_row(row![
+            conflict.service,
+            format!("{}/{}", conflict.port, conflict.protocol),
+            conflict.owner
+        ]);
+    }
+
+    table.printstd();
+    println!();
+}
+
+/// Picks a free host port for each conflict, avoiding every port the project
+/// publishes itself.
+fn remap_conflicts(
+    ports: &BTreeMap<String, ServicePorts>,
+    conflicts: &[Conflict],
+) -> Result<Vec<Remap>> {
+    let container_ports: BTreeSet<u16> = container_ports()?.iter().map(|held| held.port).collect();
+    let mut taken: BTreeSet<u16> = ports
+        .values()
+        .flat_map(|service_ports| {
+            service_ports
+                .mappings
+                .iter()
+                .flat_map(PortMapping::host_ports)
+        })
+        .collect();
+    let mut remaps = Vec::new();
+
+    for conflict in conflicts {
+        let mapping = ports[&conflict.service]
+            .mappings
+            .iter()
+            .find(|mapping| mapping.host_ports().any(|port| port == conflict.port))
+            .ok_or_else(|| {
+                format_err!(
+                    "Unable to find port {} of {}",
+                    conflict.port,
+                    conflict.service
+                )
+            })?;
+
+        if mapping.is_range() {
+            Err(format_err!(
+                "Unable to remap port {} of {}: port ranges can't be remapped",
+                conflict.port,
+                conflict.service
+            ))?
+        }
+
+        let free = (1..=REMAP_SEARCH_LIMIT)
+            .filter_map(|offset| conflict.port.checked_add(offset))
+            .find(|port| {
+                !taken.contains(port)
+                    && !container_ports.contains(port)
+                    && is_free(mapping.host_ip.as_deref(), *port, &mapping.protocol)
+            })
+            .ok_or_else(|| {
+                format_err!(
+                    "Unable to find a free port near {} for {}",
+                    conflict.port,
+                    conflict.service
+                )
+            })?;
+
+        taken.insert(free);
+        remaps.push(Remap {
+            service: conflict.service.clone(),
+            from: conflict.port,
+            to: free,
+        });
+    }
+
+    Ok(remaps)
+}
+
+/// Writes the full `ports:` list of every remapped service, along with the
+/// services remapped before, using compose's `!override` tag so the lists
+/// replace rather than extend the ones in the other files. Services remapped
+/// before that aren't in this set of files keep their entries as they are.
+fn write_overrides(
+    path: &Path,
+    ports: &BTreeMap<String, ServicePorts>,
+    remaps: &[Remap],
+) -> Result<()> {
+    let mut services = read_overrides(path)?;
+
+    for (service, service_ports) in ports {
+        let remapped = remaps.iter().any(|remap| remap.service == *service);
+
+        if !remapped && !services.contains_key(service) {
+            continue;
+        }
+
+        let entries = service_ports
+            .entries
+            .iter()
+            .map(|entry| {
+                PortMapping::parse(entry)
+                    .and_then(|mut mapping| {
+                        let remap = remaps.iter().find(|remap| {
+                            remap.service == *service && remap.from == mapping.host_ports.0
+                        })?;
+                        mapping.host_ports = (remap.to, remap.to);
+                        Some(Port::Short(mapping.to_short_syntax()))
+                    })
+                    .unwrap_or_else(|| entry.clone())
+            })
+            .collect();
+
+        services.insert(service.clone(), entries);
+    }
+
+    let header = "# Generated by `devbox start --remap`; delete it to go back to the original ports";
+    fs::write(path, overrides_yaml(header, &services)?)?;
+
+    Ok(())
+}
+
+/// Fails unless `docker-compose` is recent enough to read the overrides file,
+/// since older versions reject its `!override` tags with a YAML error.
+fn check_override_support() -> Result<()> {
+    let output = Command::new("docker-compose")
+        .args(["version", "--short"])
+        .output()?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    let mut numbers = version
+        .trim_start_matches('v')
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|number| number.parse::<u64>().unwrap_or(0));
+    let parsed = (
+        numbers.next().unwrap_or(0),
+        numbers.next().unwrap_or(0),
+        numbers.next().unwrap_or(0),
+    );
+
+    if parsed >= OVERRIDE_TAG_VERSION {
+        return Ok(());
+    }
+
+    let (major, minor, patch) = OVERRIDE_TAG_VERSION;
+    Err(format_err!(
+        "Remapping ports needs docker-compose {}.{}.{} or later, found {}; upgrade it or free the ports instead",
+        major,
+        minor,
+        patch,
+        if version.is_empty() { "an unknown version" } else { &version }
+    ))
+}
+
+/// Writes the overrides in `path` for the given services only to `copy`,
+/// returning whether there were any.
+pub(crate) fn write_overrides_for(
+    path: &Path,
+    services: &BTreeSet<String>,
+    copy: &Path,
+) -> Result<bool> {
+    let overrides: BTreeMap<String, Vec<Port>> = read_overrides(path)?
+        .into_iter()
+        .filter(|(service, _)| services.contains(service))
+        .collect();
+
+    if overrides.is_empty() {
+        return Ok(false);
+    }
+
+    let header = format!("# Generated by devbox from {}", path.display());
+    let yaml = overrides_yaml(&header, &overrides)?;
+
+    // Only rewrite the copy when the overrides changed.
+    if fs::read_to_string(copy).ok().as_deref() != Some(yaml.as_str()) {
+        if let Some(dir) = copy.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(copy, yaml)?;
+    }
+
+    Ok(true)
+}
+
+/// The `ports:` entries of each service in an overrides file.
+fn read_overrides(path: &Path) -> Result<BTreeMap<String, Vec<Port>>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+
+    Ok(ComposeFile::read(path)?
+        .compose
+        .services
+        .into_iter()
+        .map(|(name, service)| (name, service.ports))
+        .collect())
+}
+
+fn overrides_yaml(header: &str, services: &BTreeMap<String, Vec<Port>>) -> Result<String> {
+    let mut yaml = format!("{}\nservices:\n", header);
+
+    // JSON strings are valid YAML scalars, which takes care of quoting, and
+    // the long syntax is written as JSON objects, which are valid YAML too.
+    for (service, entries) in services {
+        yaml.push_str(&format!(
+            "  {}:\n    ports: !override\n",
+            serde_json::to_string(service)?
+        ));
+
+        for entry in entries {
+            yaml.push_str(&format!("      - {}\n", serde_json::to_string(entry)?));
+        }
+    }
+
+    Ok(yaml)
+}
+
+fn container_ports() -> Result<Vec<ContainerPort>> {
+    let format = format!(
+        "{{{{.Label \"{}\"}}}}\t{{{{.Label \"com.docker.compose.service\"}}}}\t{{{{.Names}}}}\t{{{{.Ports}}}}",
+        PROJECT_LABEL
+    );
+    let output = Command::new("docker")
+        .args(["ps", "--format", &format])
+        .output()?;
+
+    let mut ports = Vec::new();
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+
+        if fields.len() < 4 {
+            continue;
+        }
+
+        // e.g. "0.0.0.0:5432->5432/tcp, :::5432->5432/tcp, 9300/tcp"
+        for published in fields[3].split(", ") {
+            let (host, container) = match published.split_once("->") {
+                Some(parts) => parts,
+                None => continue,
+            };
+            let protocol = container
+                .rsplit_once('/')
+                .map_or("tcp", |(_, protocol)| protocol);
+            let range = match host
+                .rsplit_once(':')
+                .and_then(|(_, ports)| parse_range(ports))
+            {
+                Some(range) => range,
+                None => continue,
+            };
+
+            for port in range.0..=range.1 {
+                ports.push(ContainerPort {
+                    project: fields[0].to_owned(),
+                    service: fields[1].to_owned(),
+                    container: fields[2].to_owned(),
+                    port,
+                    protocol: protocol.to_owned(),
+                });
+            }
+        }
+    }
+
+    Ok(ports)
+}
+
+/// Whether a port can be bound. Ports we aren't allowed to bind are assumed
+/// to be free, since there's no telling.
+fn is_free(host_ip: Option<&str>, port: u16, protocol: &str) -> bool {
+    let ip = match host_ip {
+        Some(ip) if ip.contains(':') => format!("[{}]", ip),
+        Some(ip) => ip.to_owned(),
+        None => "0.0.0.0".to_owned(),
+    };
+    let address = format!("{}:{}", ip, port);
+
+    let result = if protocol == "udp" {
+        UdpSocket::bind(&address).map(|_| ())
+    } else {
+        TcpListener::bind(&address).map(|_| ())
+    };
+
+    match result {
+        Ok(()) => true,
+        Err(ref err) => err.kind() != ErrorKind::AddrInUse,
+    }
+}
+
+/// Describes the process listening on a port using `lsof`, when available.
+fn process_holding(port: u16, protocol: &str) -> String {
+    let mut cmd = Command::new("lsof");
+    cmd.args(["-nP", "-F", "pc"]);
+
+    if protocol == "udp" {
+        cmd.arg(format!("-iUDP:{}", port));
+    } else {
+        cmd.arg(format!("-iTCP:{}", port)).arg("-sTCP:LISTEN");
+    }
+
+    let output = match cmd.stderr(Stdio::null()).output() {
+        Ok(output) => output,
+        Err(_) => return "another process".into(),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    let pid = stdout.lines().find_map(|line| line.strip_prefix('p'));
+    let command = stdout.lines().find_map(|line| line.strip_prefix('c'));
+
+    match (pid, command) {
+        (Some(pid), Some(command)) => format!("{} (pid {})", command, pid),
+        (Some(pid), None) => format!("pid {}", pid),
+        _ => "another process".into(),
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+use toml;
+
+use errors::*;
+
+/// A named subset of a project's services, as declared in the `[profiles]`
+/// table of its `config.toml`:
+///
+/// ```toml
+/// [profiles.minimal]
+/// services = ["api", "postgres"]
+/// ```
+///
+/// The names may be devbox services or services defined only in the project's
+/// `docker-compose.yml`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Profile {
+    #[serde(skip)]
+    pub name: String,
+    pub services: Vec<String>,
+}
+
+impl Profile {
+    pub fn includes(&self, service: &str) -> bool {
+        self.services.iter().any(|name| name == service)
+    }
+}
+
+/// Parses the `[profiles]` table of a project's `config.toml`.
+pub fn parse_profiles(values: &toml::Value) -> Result<BTreeMap<String, Profile>> {
+    let mut profiles = match values.get("profiles") {
+        Some(v) => v.clone().try_into::<BTreeMap<String, Profile>>()?,
+        None => BTreeMap::new(),
+    };
+
+    for (name, profile) in &mut profiles {
+        profile.name = name.clone();
+    }
+
+    Ok(profiles)
+}
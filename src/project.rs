@@ -1,111 +1,77 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::{DirBuilder, File, OpenOptions};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use colored::*;
 use dirs::home_dir;
 use failure::ResultExt;
 use tempdir::TempDir;
 use toml;
 
+use compose::{Compose, ComposeFiles};
+use config::Config;
+use environment::{self, EnvConfig};
 use errors::*;
+use health;
+use hook::{self, Hook, HookConfig, HookTask};
+use logs::LogSettings;
+use network::{self, Network};
+use ports;
+use profile::{self, Profile};
+use secrets::{self, Secrets};
 use service::Service;
+use task::{Task, TaskMode};
+use template::{Templates, DEFAULT_TEMPLATE, TEMPLATES_DIR};
 
-const TOML_TEMPLATE: &str = r#"# Example devbox project configuration
-#
-# Devbox can automatically create external docker volumes by specifying each
-# volume as a name.
-#
-# volumes = [
-#   "mysql",
-#   "postgres"
-# ]
-#
-# Service definitions allow devbox to know how to clone or find the source code.
-#
-# * `name` - The name of the service
-# * `git` - The source of a git repository
-# * `path` - The path to the source on disk (optional)
-#
-# [services]
-# service1 = { git = "https://github.com/scrogson/service1" }
-# service2 = { path = "/path/to/service2" }
-"#;
-
-const COMPOSE_YAML_TEMPLATE: &str = r#"# This is an example docker-compose config file
-#
-# Replace the contents based on your project's requirements.
-#
-# See https://docs.docker.com/compose/compose-file for details.
-version: "3"
-
-networks:
-  example:
-    external: true
-
-volumes:
-  postgres:
-    external: true
-  mysql:
-    external: true
-
-services:
-
-  redis:
-    image: redis
-    restart: "on-failure"
-    ports:
-      - "127.0.0.1:6379:6379"
-    networks:
-      - example
-
-  mysql:
-    image: mysql:5.6
-    restart: "on-failure"
-    environment:
-      MYSQL_ALLOW_EMPTY_PASSWORD: "yes"
-    volumes:
-      - mysql:/var/lib/mysql
-    ports:
-      - "127.0.0.1:3306:3306"
-    networks:
-      - example
-
-  postgres:
-    image: postgres:9.6
-    restart: "on-failure"
-    environment:
-      POSTGRES_USER: "postgres"
-      POSTGRES_PASSWORD: "postgres"
-      PGDATA: /var/lib/postgresql/data/pgdata
-    volumes:
-      - postgres:/var/lib/postgresql/data/pgdata
-    ports:
-      - "127.0.0.1:5432:5432"
-    networks:
-      - example
-"#;
+/// A project-level hook, each task paired with the service it runs in.
+pub type ProjectHook = Hook<(Option<Service>, Task)>;
 
 #[derive(Debug)]
 pub struct Project {
+    /// The compose files from `compose_files`, layered under the project's own
+    /// `docker-compose.yml`.
+    pub base_compose_files: Vec<PathBuf>,
+    /// The merged `config.toml`, along with the files it extends and includes.
+    pub config: Config,
     pub docker_compose_file: PathBuf,
+    /// The project's own environment; see `write_env_file`.
+    pub env: BTreeMap<String, String>,
+    pub hooks: BTreeMap<String, HookConfig>,
+    pub logs: LogSettings,
     pub name: String,
+    pub networks: Vec<Network>,
+    /// The selected profile, if any; see `select_profile`.
+    pub profile: Option<Profile>,
+    pub profiles: BTreeMap<String, Profile>,
+    pub secrets: Secrets,
     pub services: Vec<Service>,
     pub volumes: Vec<String>,
 }
 
 impl Project {
     pub fn new(project_name: &str) -> Result<Self> {
-        let toml_config_path = toml_config_path(project_name)?;
+        let devbox_dir = devbox_dir(project_name)?;
         let yaml_config_path = yaml_config_path(project_name)?;
-        let value = parse_toml_config(toml_config_path)?;
+        let config = Self::load_config(project_name)?;
+
+        let value = config.value.clone();
+        let base_compose_files = parse_compose_files(&value)?;
+
+        let env_config = EnvConfig::parse(&value)?;
+        let mut env = BTreeMap::new();
+        env_config.apply(&devbox_dir, &mut env)?;
+
+        let secrets = Secrets::new(project_name, &value)?;
 
         let services = match value.get("services") {
             Some(services) => services
                 .as_table()
                 .expect("services must be in table format")
                 .iter()
+                .filter(|&(_, attributes)| !is_disabled(attributes))
                 .map(|(name, attributes)| {
                     let hooks = None;
                     let name = name.to_owned();
@@ -119,11 +85,15 @@ impl Project {
                     let tasks = None;
 
                     Service {
+                        base_compose_files: base_compose_files.clone(),
+                        env: env.clone(),
                         hooks,
                         name,
                         path,
+                        project_env: env.clone(),
                         project_name,
                         repo,
+                        secrets: secrets.clone(),
                         tasks,
                     }
                 })
@@ -142,25 +112,66 @@ impl Project {
             None => Vec::new(),
         };
 
+        let hooks = hook::parse_hooks(&value)?;
+        let networks = network::parse_networks(&value, project_name)?;
+        let profiles = profile::parse_profiles(&value)?;
+
+        let logs = match value.get("logs") {
+            Some(logs) => logs.clone().try_into::<LogSettings>()?,
+            None => LogSettings::default(),
+        };
+
         env::set_var("COMPOSE_PROJECT_NAME", &project_name);
         env::set_var("COMPOSE_FILE", &yaml_config_path);
 
         Ok(Project {
+            base_compose_files,
+            config,
             docker_compose_file: yaml_config_path,
+            env,
+            hooks,
+            logs,
             name: project_name.to_owned(),
+            networks,
+            profile: None,
+            profiles,
+            secrets,
             services,
             volumes,
         })
     }
 
-    pub fn init(name: &str) -> Result<()> {
+    /// Loads the project's config and its local overrides, without checking
+    /// the services, hooks or anything else they declare.
+    pub fn load_config(project_name: &str) -> Result<Config> {
+        let path = toml_config_path(project_name)?;
+        let mut config = Config::load(&path, &config_variables(project_name)?)
+            .context("Couldn't load devbox project config")?;
+        config.overlay(&local_config_path(project_name)?)?;
+
+        Ok(config)
+    }
+
+    /// Creates the project from a template, the default one unless another is
+    /// named. Files that already exist are left alone.
+    pub fn init(name: &str, template: Option<&str>) -> Result<()> {
+        if name == TEMPLATES_DIR {
+            Err(format_err!("`{}` is reserved for project templates", name))?
+        }
+
+        let template = Templates::new()?.find(template.unwrap_or(DEFAULT_TEMPLATE))?;
         let devbox_dir = devbox_dir(name)?;
-        let toml_config = toml_config_path(name)?;
-        let yaml_config = yaml_config_path(name)?;
 
         ensure_directory_exists(&devbox_dir);
-        create_file_if_not_exists(&toml_config, TOML_TEMPLATE)?;
-        create_file_if_not_exists(&yaml_config, COMPOSE_YAML_TEMPLATE)?;
+
+        for (path, contents) in template.render(name, name)? {
+            let path = devbox_dir.join(path);
+
+            if let Some(parent) = path.parent() {
+                ensure_directory_exists(&parent.to_path_buf());
+            }
+            create_file_if_not_exists(&path, &contents)?;
+        }
 
         Ok(())
     }
@@ -189,29 +200,297 @@ impl Project {
         Ok(())
     }
 
+    /// The project's own `config.toml`.
+    pub fn config_file(&self) -> Result<PathBuf> {
+        toml_config_path(&self.name)
+    }
+
     pub fn find_service(&mut self, name: &str) -> Result<&mut Service> {
         let service = self.services
             .iter_mut()
             .find(|ref mut service| service.name == name)
             .ok_or_else(|| ServiceNotFound(name.to_owned()))?;
 
-        let _ = service.rehydrate_from_devbox_toml();
+        service.rehydrate_from_devbox_toml()?;
 
         Ok(service)
     }
+
+    /// Resolves the tasks attached to a project-level `lifecycle` hook along
+    /// with the service each one runs in. Host tasks without a service run
+    /// from the project's devbox directory.
+    pub fn hook(&mut self, lifecycle: &str) -> Result<Option<ProjectHook>> {
+        let config = match self.hooks.get(lifecycle) {
+            Some(config) => config.clone(),
+            None => return Ok(None),
+        };
+
+        let mut tasks = Vec::new();
+
+        for entry in config.tasks() {
+            match *entry {
+                HookTask::Inline(ref inline) => {
+                    let task = inline.to_task();
+
+                    match inline.service {
+                        Some(ref name) => tasks.push((Some(self.find_service(name)?.clone()), task)),
+                        None if task.mode == Some(TaskMode::Host) => tasks.push((None, task)),
+                        None => Err(format_err!(
+                            "Inline project hook task `{}` needs a `service` unless its mode is `host`",
+                            task.name
+                        ))?,
+                    }
+                }
+                ref entry => {
+                    let name = entry.task_name().unwrap_or_default();
+                    let mut parts = name.splitn(2, ':');
+                    let (service_name, task_name) = match (parts.next(), parts.next()) {
+                        (Some(service), Some(task)) => (service, task),
+                        _ => Err(format_err!(
+                            "Project hook task `{}` must be written as `service:task`",
+                            name
+                        ))?,
+                    };
+
+                    let service = self.find_service(service_name)?;
+                    let task = match service.find_task(task_name) {
+                        Some(task) => task,
+                        None => Err(service.task_not_found(task_name))?,
+                    };
+
+                    tasks.push((Some(service.clone()), task));
+                }
+            }
+        }
+
+        let on_failure = config.on_failure();
+
+        Ok(Some(Hook { tasks, on_failure }))
+    }
+
+    /// Runs the project-level hooks attached to `lifecycle`.
+    pub fn run_lifecycle_hooks(&mut self, lifecycle: &str) -> Result<()> {
+        let hook = match self.hook(lifecycle)? {
+            Some(hook) => hook,
+            None => return Ok(()),
+        };
+
+        println!("{} Running project {} hooks", "INFO".green(), lifecycle);
+
+        let owner = format!("project {}", self.name);
+        let dir = devbox_dir(&self.name)?;
+
+        for (service, task) in &hook.tasks {
+            let outcome = match service {
+                Some(service) => service.spawn_task(task, TaskMode::Run, None),
+                None => task.host_command(&dir, &self.env, &self.secrets)
+                    .and_then(|mut cmd| Ok(cmd.spawn()?.wait()?)),
+            };
+
+            hook::check_task(&owner, lifecycle, hook.on_failure, task, outcome)?;
+        }
+
+        Ok(())
+    }
+
+    /// The project's compose file and generated network overrides, followed by
+    /// every service overlay and the generated port overrides. The overlays
+    /// are given to compose as copies with their relative paths made absolute.
+    pub fn compose_files(&self) -> ComposeFiles {
+        let mut services: Vec<&Service> = self.services.iter().collect();
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let dir = self.docker_compose_file.parent().unwrap_or_else(|| Path::new("."));
+        let mut files = self.infrastructure_files().file(network::overrides_file(dir));
+
+        for service in services {
+            files = files.resolved_overlay(service, dir);
+        }
+
+        files.port_overrides(dir, &ComposeFiles::port_overrides_copy(dir, None))
+    }
+
+    /// The project's compose files parsed and merged, the service overlays
+    /// and generated overrides included.
+    pub fn compose(&self) -> Result<Compose> {
+        self.compose_files().load()
+    }
+
+    /// Checks that the host ports the services in the selected profile
+    /// publish are free, moving the ones that aren't when `remap` is set.
+    pub fn check_ports(&self, remap: bool) -> Result<()> {
+        let dir = self.docker_compose_file.parent().unwrap_or_else(|| Path::new("."));
+        let files = self.compose_files();
+
+        ports::check_ports(&self.name, dir, &files, |service| self.includes(service), remap)
+            .map(|_| ())
+    }
+
+    /// Limits the project to the services of a profile, for the commands that
+    /// operate on all of them.
+    pub fn select_profile(&mut self, name: &str) -> Result<()> {
+        let profile = self.profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ProfileNotFound(name.to_owned()))?;
+
+        let infrastructure = self.infrastructure_files().services()?;
+
+        for service in &profile.services {
+            // Disabled services are still known, just never selected.
+            let known = infrastructure.contains(service)
+                || self.config.value.get("services").and_then(|s| s.get(service)).is_some();
+
+            if !known {
+                Err(format_err!(
+                    "Profile {} lists {}, which is neither a service nor defined in docker-compose.yml",
+                    name,
+                    service
+                ))?
+            }
+        }
+
+        self.profile = Some(profile);
+
+        Ok(())
+    }
+
+    /// Whether a service is part of the selected profile, which is true of
+    /// every service when there's none.
+    pub fn includes(&self, service: &str) -> bool {
+        match self.profile {
+            Some(ref profile) => profile.includes(service),
+            None => true,
+        }
+    }
+
+    /// The services in the selected profile.
+    pub fn selected_services(&mut self) -> Vec<&mut Service> {
+        let profile = self.profile.clone();
+
+        self.services
+            .iter_mut()
+            .filter(|service| match profile {
+                Some(ref profile) => profile.includes(&service.name),
+                None => true,
+            })
+            .collect()
+    }
+
+    /// Writes the project's environment to the `.env` file compose reads, for
+    /// the commands that hand it to compose. Any hand-written `.env` is left
+    /// alone unless the config declares an environment, and secrets are
+    /// resolved when they're needed rather than written down.
+    pub fn write_env_file(&self) -> Result<()> {
+        if EnvConfig::parse(&self.config.value)?.is_empty() {
+            return Ok(());
+        }
+
+        let dir = devbox_dir(&self.name)?;
+        environment::write_env_file(&dir.join(".env"), &secrets::without_secrets(&self.env))
+    }
+
+    /// Writes the compose overrides for the aliases in `[networks]`. Only the
+    /// commands that create containers need them up to date.
+    pub fn write_network_overrides(&self) -> Result<()> {
+        let dir = self.docker_compose_file.parent().unwrap_or_else(|| Path::new("."));
+        let declared = self.infrastructure_files().declared_networks();
+        network::write_overrides(dir, &self.networks, &declared)
+    }
+
+    pub fn find_network(&self, name: &str) -> Result<&Network> {
+        self.networks
+            .iter()
+            .find(|network| network.name == name)
+            .ok_or_else(|| NetworkNotFound(name.to_owned()).into())
+    }
+
+    /// A `docker-compose` command that sees the project and all of its
+    /// services. Values that refer to secrets are left out; see
+    /// `resolved_env`.
+    pub fn docker_compose(&self) -> Result<Command> {
+        let mut cmd = self.compose_files().command("docker-compose")?;
+        cmd.envs(secrets::without_secrets(&self.env));
+        Ok(cmd)
+    }
+
+    /// The project's environment with its secrets looked up.
+    pub fn resolved_env(&self) -> Result<BTreeMap<String, String>> {
+        self.secrets.resolve(&self.env)
+    }
+
+    /// Every compose service of the project and its overlays that is in the
+    /// selected profile.
+    pub fn compose_services(&self) -> Result<Vec<String>> {
+        Ok(self
+            .compose_files()
+            .services()?
+            .into_iter()
+            .filter(|service| self.includes(service))
+            .collect())
+    }
+
+    /// The services defined by the project's own compose file, as opposed to
+    /// the service overlays, that are in the selected profile.
+    pub fn infrastructure_services(&self) -> Result<Vec<String>> {
+        Ok(self
+            .infrastructure_files()
+            .services()?
+            .into_iter()
+            .filter(|service| self.includes(service))
+            .collect())
+    }
+
+    /// The project's own compose file over its `compose_files`.
+    fn infrastructure_files(&self) -> ComposeFiles {
+        ComposeFiles::layered(&self.base_compose_files, &self.docker_compose_file)
+    }
+
+    /// The ids of the project's containers, including those of its services.
+    pub fn containers(&self) -> Result<Vec<String>> {
+        health::compose_containers(self.docker_compose()?.arg("ps").arg("-q"))
+    }
+
+    /// Waits for the running containers of the given compose services to be
+    /// healthy.
+    pub fn wait_until_healthy(&self, services: &[String]) -> Result<()> {
+        if services.is_empty() {
+            return Ok(());
+        }
+
+        let containers =
+            health::compose_containers(self.docker_compose()?.arg("ps").arg("-q").args(services))?;
+
+        health::wait_until_healthy(&self.name, &containers)
+    }
 }
 
-fn parse_toml_config(path: PathBuf) -> Result<toml::Value> {
-    let mut contents = String::new();
+/// The compose files listed in `compose_files`, which every file must exist.
+fn parse_compose_files(values: &toml::Value) -> Result<Vec<PathBuf>> {
+    let files = match values.get("compose_files") {
+        Some(v) => v.clone().try_into::<Vec<PathBuf>>()?,
+        None => Vec::new(),
+    };
+
+    for file in &files {
+        if !file.exists() {
+            Err(format_err!("Compose file {} does not exist", file.display()))?
+        }
+    }
+
+    Ok(files)
+}
 
-    File::open(path)
-        .context("Couldn't find devbox project config")?
-        .read_to_string(&mut contents)
-        .context("Unable to read config file")?;
+/// The `${...}` variables available in every config file of a project.
+pub(crate) fn config_variables(project_name: &str) -> Result<BTreeMap<String, String>> {
+    let dir = devbox_dir(project_name)?;
+    let home = dir.parent().unwrap_or(&dir);
 
-    let value = toml::from_str::<toml::Value>(&contents)?;
+    let mut variables = BTreeMap::new();
+    variables.insert("project.name".to_owned(), project_name.to_owned());
+    variables.insert("devbox.home".to_owned(), home.to_string_lossy().into_owned());
 
-    Ok(value)
+    Ok(variables)
 }
 
 pub fn devbox_dir(name: &str) -> Result<PathBuf> {
@@ -226,7 +505,23 @@ fn read_file(path: &PathBuf) -> Result<String> {
     Ok(contents)
 }
 
-fn toml_config_path(name: &str) -> Result<PathBuf> {
+/// A service can be switched off with `disabled = true`, typically in
+/// `config.local.toml`.
+fn is_disabled(attributes: &toml::Value) -> bool {
+    attributes
+        .get("disabled")
+        .and_then(|disabled| disabled.as_bool())
+        .unwrap_or(false)
+}
+
+/// Personal overrides of the project's `config.toml`, kept out of version
+/// control.
+pub fn local_config_path(name: &str) -> Result<PathBuf> {
+    Ok(devbox_dir(name)?.join("config.local.toml"))
+}
+
+/// The project's own `config.toml`.
+pub fn toml_config_path(name: &str) -> Result<PathBuf> {
     Ok(devbox_dir(name)?.join("config.toml"))
 }
 
@@ -0,0 +1,279 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_json;
+
+use errors::*;
+use service::{COMPOSE_PATH, TOML_PATH};
+
+/// The kinds of project `devbox init` recognizes, by the file at their root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stack {
+    Elixir,
+    Node,
+    Ruby,
+    Rust,
+    Go,
+    /// Only a `Dockerfile`.
+    Docker,
+}
+
+impl fmt::Display for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Stack::Elixir => "Elixir",
+            Stack::Node => "Node.js",
+            Stack::Ruby => "Ruby",
+            Stack::Rust => "Rust",
+            Stack::Go => "Go",
+            Stack::Docker => "Docker",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// A task `devbox init` writes to a new service's config.
+struct StarterTask {
+    name: &'static str,
+    description: &'static str,
+    exec: &'static [&'static str],
+}
+
+impl Stack {
+    /// Recognizes the stack of the source in `dir`, if any.
+    pub fn detect(dir: &Path) -> Option<Stack> {
+        const MARKERS: &[(&str, Stack)] = &[
+            ("mix.exs", Stack::Elixir),
+            ("package.json", Stack::Node),
+            ("Gemfile", Stack::Ruby),
+            ("Cargo.toml", Stack::Rust),
+            ("go.mod", Stack::Go),
+            ("Dockerfile", Stack::Docker),
+        ];
+
+        MARKERS
+            .iter()
+            .find(|&&(file, _)| dir.join(file).exists())
+            .map(|&(_, stack)| stack)
+    }
+
+    /// The image to run when the service has no `Dockerfile`.
+    fn image(self) -> &'static str {
+        match self {
+            Stack::Elixir => "elixir:latest",
+            Stack::Node => "node:lts",
+            Stack::Ruby => "ruby:latest",
+            Stack::Rust => "rust:latest",
+            Stack::Go => "golang:latest",
+            Stack::Docker => "alpine:3",
+        }
+    }
+
+    fn command(self) -> &'static [&'static str] {
+        match self {
+            Stack::Elixir => &["mix", "phx.server"],
+            Stack::Node => &["npm", "start"],
+            Stack::Ruby => &["bundle", "exec", "rails", "server", "-b", "0.0.0.0"],
+            Stack::Rust => &["cargo", "run"],
+            Stack::Go => &["go", "run", "."],
+            Stack::Docker => &[],
+        }
+    }
+
+    fn tasks(self) -> Vec<StarterTask> {
+        let task = |name, description, exec| StarterTask {
+            name,
+            description,
+            exec,
+        };
+
+        match self {
+            Stack::Elixir => vec![
+                task("deps", "Fetch dependencies", &["mix", "deps.get"]),
+                task("test", "Run the tests", &["mix", "test"]),
+                task("console", "Open an IEx console", &["iex", "-S", "mix"]),
+                task("migrate", "Migrate the database", &["mix", "ecto.migrate"]),
+            ],
+            Stack::Node => vec![
+                task("deps", "Install dependencies", &["npm", "install"]),
+                task("test", "Run the tests", &["npm", "test"]),
+                task("console", "Open a Node.js console", &["node"]),
+                task("migrate", "Migrate the database", &["npm", "run", "migrate"]),
+            ],
+            Stack::Ruby => vec![
+                task("deps", "Install gems", &["bundle", "install"]),
+                task("test", "Run the tests", &["bundle", "exec", "rake", "test"]),
+                task("console", "Open a Rails console", &["bundle", "exec", "rails", "console"]),
+                task("migrate", "Migrate the database", &["bundle", "exec", "rails", "db:migrate"]),
+            ],
+            Stack::Rust => vec![
+                task("deps", "Fetch dependencies", &["cargo", "fetch"]),
+                task("test", "Run the tests", &["cargo", "test"]),
+                task("console", "Open a shell", &["sh"]),
+            ],
+            Stack::Go => vec![
+                task("deps", "Download modules", &["go", "mod", "download"]),
+                task("test", "Run the tests", &["go", "test", "./..."]),
+                task("console", "Open a shell", &["sh"]),
+            ],
+            Stack::Docker => vec![task("console", "Open a shell", &["sh"])],
+        }
+    }
+}
+
+/// The `.devbox` files `devbox init` generates for a service.
+#[derive(Clone, Debug)]
+pub struct Scaffold {
+    pub name: String,
+    pub stack: Stack,
+    /// The source directory.
+    pub dir: PathBuf,
+    /// The project network to attach the service to.
+    pub network: Option<String>,
+}
+
+impl Scaffold {
+    pub fn new<P: Into<PathBuf>>(name: &str, dir: P, network: Option<String>) -> Result<Self> {
+        let dir = dir.into();
+        let stack = Stack::detect(&dir).ok_or_else(|| {
+            format_err!(
+                "Unable to tell what kind of service {} is; expected a Dockerfile, package.json, Cargo.toml, mix.exs, Gemfile, or go.mod",
+                dir.display()
+            )
+        })?;
+
+        Ok(Scaffold {
+            name: name.to_owned(),
+            stack,
+            dir,
+            network,
+        })
+    }
+
+    /// Writes the service's `.devbox/docker-compose.yml` and
+    /// `.devbox/config.toml`, returning the files written. Existing files are
+    /// only replaced when `force` is set.
+    pub fn write(&self, force: bool) -> Result<Vec<PathBuf>> {
+        let files = [
+            (self.dir.join(COMPOSE_PATH), self.compose_yaml()),
+            (self.dir.join(TOML_PATH), self.config_toml()),
+        ];
+
+        if !force {
+            if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+                Err(format_err!(
+                    "{} already exists; pass --force to replace it",
+                    path.display()
+                ))?
+            }
+        }
+
+        let mut written = Vec::new();
+
+        for (path, contents) in files.iter() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, contents)?;
+            written.push(path.clone());
+        }
+
+        Ok(written)
+    }
+
+    /// An overlay that builds or runs the service with its source mounted.
+    /// Paths are relative to the `.devbox` directory, which keeps the file
+    /// usable from any checkout: the service's own commands run from there,
+    /// and project-wide ones stack a copy with the paths made absolute.
+    pub fn compose_yaml(&self) -> String {
+        let mut yaml = format!(
+            "# Generated by devbox init for a {} service; adjust it to fit.\n",
+            self.stack
+        );
+        yaml.push_str("version: \"3\"\n\nservices:\n");
+        yaml.push_str(&format!("  {}:\n", quote(&self.name)));
+
+        if self.dir.join("Dockerfile").exists() {
+            yaml.push_str("    build: ..\n");
+        } else {
+            yaml.push_str(&format!("    image: {}\n", quote(self.stack.image())));
+
+            let command = self.stack.command();
+            if !command.is_empty() {
+                yaml.push_str(&format!("    command: {}\n", list(command)));
+            }
+        }
+
+        yaml.push_str("    working_dir: /app\n    volumes:\n      - ..:/app\n");
+
+        if let Some(ref network) = self.network {
+            yaml.push_str(&format!("    networks:\n      - {}\n", quote(network)));
+            yaml.push_str(&format!(
+                "\nnetworks:\n  {}:\n    external: true\n",
+                quote(network)
+            ));
+        }
+
+        yaml
+    }
+
+    /// Starter tasks for the stack, with dependencies fetched after cloning.
+    pub fn config_toml(&self) -> String {
+        let tasks = self.stack.tasks();
+        let mut toml = format!(
+            "# Generated by devbox init for a {} service; adjust it to fit.\n",
+            self.stack
+        );
+
+        toml.push_str("tasks = [\n");
+        for task in &tasks {
+            toml.push_str(&format!(
+                "  {{ name = {}, description = {}, exec = {} }},\n",
+                quote(task.name),
+                quote(task.description),
+                list(task.exec)
+            ));
+        }
+        toml.push_str("]\n");
+
+        if tasks.iter().any(|task| task.name == "deps") {
+            toml.push_str("\n[hooks]\nafter-clone = [\"deps\"]\n");
+        }
+
+        toml
+    }
+}
+
+/// The URL of the `origin` remote of the repository at `dir`, if it has one.
+pub fn git_origin(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["remote", "get-url", "origin"])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let url = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+
+    if url.is_empty() {
+        None
+    } else {
+        Some(url)
+    }
+}
+
+// JSON strings are valid YAML scalars and TOML basic strings alike.
+pub(crate) fn quote(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_default()
+}
+
+fn list(items: &[&str]) -> String {
+    let items: Vec<String> = items.iter().map(|item| quote(item)).collect();
+    format!("[{}]", items.join(", "))
+}
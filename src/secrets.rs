@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use serde_json;
+use toml;
+
+use errors::*;
+use project;
+
+/// Env values written as `secret://<provider>/<key>` are looked up in a
+/// secret provider when a service starts or a task runs.
+pub const SECRET_SCHEME: &str = "secret://";
+
+/// Where the local provider gets its passphrase without prompting.
+pub const PASSPHRASE_VAR: &str = "DEVBOX_SECRETS_PASSPHRASE";
+
+/// A store that secret values can be read from.
+pub trait SecretProvider {
+    fn get(&self, key: &str) -> Result<String>;
+
+    fn set(&self, _key: &str, _value: &str) -> Result<()> {
+        Err(format_err!("This secret provider is read-only"))
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Err(format_err!("This secret provider can't list its secrets"))
+    }
+}
+
+/// Secrets kept in `~/.config/devbox/<project>/secrets.enc`, a JSON document
+/// encrypted with `openssl` using a passphrase. The decrypted secrets only
+/// ever live in memory.
+pub struct LocalProvider {
+    path: PathBuf,
+    passphrase: Mutex<Option<String>>,
+}
+
+impl fmt::Debug for LocalProvider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LocalProvider")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl LocalProvider {
+    pub fn new(project_name: &str) -> Result<Self> {
+        Ok(LocalProvider {
+            path: project::devbox_dir(project_name)?.join("secrets.enc"),
+            passphrase: Mutex::new(None),
+        })
+    }
+
+    /// The passphrase from the environment, or asked for once per run.
+    fn passphrase(&self) -> Result<String> {
+        let mut passphrase = self
+            .passphrase
+            .lock()
+            .map_err(|_| format_err!("Unable to read the secrets passphrase"))?;
+
+        if let Some(ref passphrase) = *passphrase {
+            return Ok(passphrase.clone());
+        }
+
+        let value = match env::var(PASSPHRASE_VAR) {
+            Ok(value) => value,
+            Err(_) => {
+                let value = read_hidden("Passphrase for local secrets: ")?;
+
+                if !self.path.exists() && read_hidden("Confirm passphrase: ")? != value {
+                    Err(format_err!("The passphrases don't match"))?
+                }
+
+                value
+            }
+        };
+
+        *passphrase = Some(value.clone());
+
+        Ok(value)
+    }
+
+    fn load(&self) -> Result<BTreeMap<String, String>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+
+        let output = Command::new("openssl")
+            .args(["enc", "-d", "-aes-256-cbc", "-pbkdf2", "-salt", "-pass"])
+            .arg(format!("env:{}", PASSPHRASE_VAR))
+            .arg("-in")
+            .arg(&self.path)
+            .env(PASSPHRASE_VAR, self.passphrase()?)
+            .stderr(Stdio::null())
+            .output()?;
+
+        if !output.status.success() {
+            Err(format_err!(
+                "Unable to decrypt {}; is the passphrase right?",
+                self.path.display()
+            ))?
+        }
+
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    fn save(&self, secrets: &BTreeMap<String, String>) -> Result<()> {
+        let tmp = self.path.with_extension("enc.tmp");
+
+        let mut child = Command::new("openssl")
+            .args(["enc", "-aes-256-cbc", "-pbkdf2", "-salt", "-pass"])
+            .arg(format!("env:{}", PASSPHRASE_VAR))
+            .arg("-out")
+            .arg(&tmp)
+            .env(PASSPHRASE_VAR, self.passphrase()?)
+            .stdin(Stdio::piped())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&serde_json::to_vec(secrets)?)?;
+        }
+
+        if !child.wait()?.success() {
+            let _ = fs::remove_file(&tmp);
+            Err(format_err!("Unable to encrypt {}", self.path.display()))?
+        }
+
+        set_owner_only(&tmp)?;
+        fs::rename(&tmp, &self.path)?;
+
+        Ok(())
+    }
+}
+
+impl SecretProvider for LocalProvider {
+    fn get(&self, key: &str) -> Result<String> {
+        self.load()?
+            .remove(key)
+            .ok_or_else(|| format_err!("No local secret named {}", key))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut secrets = self.load()?;
+        secrets.insert(key.to_owned(), value.to_owned());
+        self.save(&secrets)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.load()?.keys().cloned().collect())
+    }
+}
+
+/// Secrets from the `pass` password store, read with `pass show`.
+#[derive(Debug)]
+pub struct PassProvider;
+
+impl SecretProvider for PassProvider {
+    fn get(&self, key: &str) -> Result<String> {
+        let output = Command::new("pass")
+            .arg("show")
+            .arg(key)
+            .stderr(Stdio::inherit())
+            .output()?;
+
+        if !output.status.success() {
+            Err(format_err!("Unable to read {} from pass", key))?
+        }
+
+        // Following pass's convention, the password is the first line.
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_owned())
+    }
+}
+
+/// Secrets printed by a command, configured in the project's `config.toml`:
+///
+/// ```toml
+/// [secrets.vault]
+/// command = ["vault", "kv", "get", "-field=value", "secret/{key}"]
+/// ```
+///
+/// `{key}` is replaced by the key of the secret, which is appended to the
+/// command when there's no `{key}`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExecProvider {
+    pub command: Vec<String>,
+}
+
+impl SecretProvider for ExecProvider {
+    fn get(&self, key: &str) -> Result<String> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| format_err!("The secret provider command is empty"))?;
+
+        let mut cmd = Command::new(program);
+
+        if self.command.iter().any(|arg| arg.contains("{key}")) {
+            cmd.args(args.iter().map(|arg| arg.replace("{key}", key)));
+        } else {
+            cmd.args(args).arg(key);
+        }
+
+        let output = cmd.stderr(Stdio::inherit()).output()?;
+
+        if !output.status.success() {
+            Err(format_err!("Unable to read secret {} from `{}`", key, program))?
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim_end_matches(['\r', '\n'])
+            .to_owned())
+    }
+}
+
+/// The secret providers available to a project: `local`, `pass`, and the
+/// exec providers in the `[secrets]` table of its `config.toml`.
+#[derive(Clone, Debug)]
+pub struct Secrets {
+    local: Arc<LocalProvider>,
+    exec: BTreeMap<String, ExecProvider>,
+}
+
+impl Secrets {
+    pub fn new(project_name: &str, values: &toml::Value) -> Result<Self> {
+        let exec = match values.get("secrets") {
+            Some(v) => v.clone().try_into::<BTreeMap<String, ExecProvider>>()?,
+            None => BTreeMap::new(),
+        };
+
+        Ok(Secrets {
+            local: Arc::new(LocalProvider::new(project_name)?),
+            exec,
+        })
+    }
+
+    pub fn local(&self) -> &LocalProvider {
+        &self.local
+    }
+
+    pub fn provider(&self, name: &str) -> Result<&dyn SecretProvider> {
+        match name {
+            "local" => Ok(&*self.local),
+            "pass" => Ok(&PassProvider),
+            name => match self.exec.get(name) {
+                Some(provider) => Ok(provider),
+                None => Err(format_err!(
+                    "Unknown secret provider `{}`; add it under [secrets] in config.toml",
+                    name
+                )),
+            },
+        }
+    }
+
+    /// Replaces every `secret://` value of `env` with the secret it refers to.
+    pub fn resolve(&self, env: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>> {
+        let mut resolved = BTreeMap::new();
+
+        for (key, value) in env {
+            let value = match value.strip_prefix(SECRET_SCHEME) {
+                Some(reference) => {
+                    let (provider, secret) = reference.split_once('/').ok_or_else(|| {
+                        format_err!(
+                            "The value of {} must look like {}<provider>/<key>",
+                            key,
+                            SECRET_SCHEME
+                        )
+                    })?;
+
+                    self.provider(provider)?.get(secret)?
+                }
+                None => value.clone(),
+            };
+
+            resolved.insert(key.clone(), value);
+        }
+
+        Ok(resolved)
+    }
+}
+
+/// `env` without the values that refer to secrets, for places where the
+/// values are written down or shouldn't need a passphrase.
+pub fn without_secrets(env: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    env.iter()
+        .filter(|&(_, value)| !value.starts_with(SECRET_SCHEME))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Reads a line from the terminal without echoing it.
+pub fn read_hidden(prompt: &str) -> Result<String> {
+    eprint!("{}", prompt);
+
+    let stty = |arg: &str| {
+        File::open("/dev/tty").and_then(|tty| Command::new("stty").arg(arg).stdin(tty).status())
+    };
+
+    let echo_off = stty("-echo").map(|status| status.success()).unwrap_or(false);
+    let mut line = String::new();
+    let read = ::std::io::stdin().read_line(&mut line);
+
+    if echo_off {
+        let _ = stty("echo");
+        eprintln!();
+    }
+
+    read?;
+
+    Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+#[cfg(unix)]
+fn set_owner_only(path: &::std::path::Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_owner_only(_path: &::std::path::Path) -> Result<()> {
+    Ok(())
+}
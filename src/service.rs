@@ -1,10 +1,9 @@
 use std::collections::BTreeMap;
 use std::env;
 use std::ffi::OsStr;
-use std::fs::File;
-use std::io::prelude::*;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, ExitStatus};
+use std::time::Instant;
 
 use colored::*;
 use failure::ResultExt;
@@ -12,47 +11,49 @@ use prettytable::format;
 use prettytable::Table;
 use toml;
 
+use compose::ComposeFiles;
+use config::Config;
+use environment::EnvConfig;
 use errors::*;
+use health;
+use hook::{self, Hook, HookConfig, HookTask, InlineTask};
+use network;
+use output::{self, Prefix};
+use ports;
 use project;
-use task::Task;
+use secrets::{self, Secrets};
+use task::{self, Task, TaskMode, TaskResult};
 
-const COMPOSE_PATH: &str = ".devbox/docker-compose.yml";
-const TOML_PATH: &str = ".devbox/config.toml";
+pub(crate) const COMPOSE_PATH: &str = ".devbox/docker-compose.yml";
+pub(crate) const TOML_PATH: &str = ".devbox/config.toml";
 
-pub fn cmd<S: AsRef<OsStr>>(program: S, service: &Service) -> Command {
-    let devbox_compose_file = env::var("COMPOSE_FILE").unwrap();
-    let service_compose_file = service.devbox_compose_file();
-
-    let mut cmd = Command::new(program);
-
-    cmd.arg("-f")
-        .arg(&devbox_compose_file)
-        .arg("-f")
-        .arg(&service_compose_file)
-        .arg("--project-directory")
-        .arg(&service_compose_file.parent().unwrap());
-
-    cmd
+pub fn cmd<S: AsRef<OsStr>>(program: S, service: &Service) -> Result<Command> {
+    let mut cmd = service.compose_files().command(program)?;
+    cmd.envs(secrets::without_secrets(&service.env));
+    Ok(cmd)
 }
 
 #[derive(Clone, Debug)]
 pub struct Service {
-    pub hooks: Option<BTreeMap<String, Vec<Task>>>,
+    pub base_compose_files: Vec<PathBuf>,
+    /// The environment of the service's compose commands and tasks: the
+    /// project's environment with the service's own layered over it.
+    pub env: BTreeMap<String, String>,
+    pub hooks: Option<BTreeMap<String, HookConfig>>,
     pub name: String,
     pub repo: Option<String>,
     pub path: Option<PathBuf>,
+    pub project_env: BTreeMap<String, String>,
     pub project_name: String,
+    pub secrets: Secrets,
     pub tasks: Option<Vec<Task>>,
 }
 
 impl Service {
     pub fn rehydrate_from_devbox_toml(&mut self) -> Result<()> {
-        let mut contents = String::new();
+        let path = self.devbox_toml_file();
 
-        if let Ok(mut file) = File::open(self.devbox_toml_file()) {
-            file.read_to_string(&mut contents)
-                .context("Unable to read config file")?;
-        } else {
+        if !path.exists() {
             println!(
                 "{} Config file not found, no tasks or hooks are defined for {}",
                 "WARN".yellow(),
@@ -61,22 +62,41 @@ impl Service {
             return Ok(());
         }
 
-        if let Ok(values) = toml::from_str(&contents) {
-            let _ = self.insert_tasks(&values);
-            let _ = self.insert_hooks(&values);
-        }
+        let mut variables = project::config_variables(&self.project_name)?;
+        variables.insert("service.name".to_owned(), self.name.clone());
+        variables.insert(
+            "service.path".to_owned(),
+            self.source_path().to_string_lossy().into_owned(),
+        );
+
+        let values = Config::load(&path, &variables)?.value;
+
+        self.insert_tasks(&values)?;
+        self.insert_hooks(&values)?;
+        self.insert_env(&values)?;
 
         Ok(())
     }
 
-    pub fn start(&self) -> Result<()> {
+    pub fn start(&mut self) -> Result<()> {
         if self.devbox_compose_file().exists() {
-            let _ = cmd("docker-compose", self)
+            self.run_lifecycle_hooks("before-start")?;
+
+            let status = cmd("docker-compose", self)?
+                .envs(self.secrets.resolve(&self.env)?)
                 .arg("up")
                 .arg("-d")
                 .arg(&self.name)
                 .spawn()?
-                .wait();
+                .wait()?;
+
+            if !status.success() {
+                Err(format_err!("Failed to start {}", self.name))?
+            }
+
+            println!("{} Waiting for {} to become healthy", "INFO".green(), self.name);
+            self.wait_until_healthy()?;
+            self.run_lifecycle_hooks("after-start")?;
 
             return Ok(());
         }
@@ -87,9 +107,11 @@ impl Service {
         ))?
     }
 
-    pub fn stop(&self) -> Result<()> {
+    pub fn stop(&mut self) -> Result<()> {
         if self.devbox_compose_file().exists() {
-            let _ = cmd("docker-compose", self)
+            self.run_lifecycle_hooks("before-stop")?;
+
+            let _ = cmd("docker-compose", self)?
                 .arg("stop")
                 .arg(&self.name)
                 .spawn()?
@@ -104,11 +126,22 @@ impl Service {
         ))?
     }
 
+    fn wait_until_healthy(&self) -> Result<()> {
+        let containers = health::compose_containers(
+            cmd("docker-compose", self)?
+                .arg("ps")
+                .arg("-q")
+                .arg(&self.name),
+        )?;
+
+        health::wait_until_healthy(&self.name, &containers)
+    }
+
     pub fn build(&mut self) -> Result<()> {
         if self.devbox_compose_file().exists() {
             self.run_lifecycle_hooks("before-build")?;
 
-            let _ = cmd("docker-compose", self)
+            let _ = cmd("docker-compose", self)?
                 .arg("build")
                 .arg(&self.name)
                 .spawn()?
@@ -129,23 +162,29 @@ impl Service {
         self.source_path().exists()
     }
 
-    pub fn clone_repo(&self) -> Result<()> {
+    pub fn clone_repo(&mut self) -> Result<()> {
         if self.path_exists() {
             eprintln!("{} already exists, fetching updates...", self.name);
             self.update_repo()
         } else {
-            match self.repo {
-                Some(ref repo) => {
-                    let _ = Command::new("git")
-                        .arg("clone")
-                        .arg(repo)
-                        .arg(self.source_path())
-                        .spawn()?
-                        .wait();
-                    Ok(())
-                }
-                None => Err(format_err!("No repository configured for {}", self.name)),
+            let repo = self.repo
+                .clone()
+                .ok_or_else(|| format_err!("No repository configured for {}", self.name))?;
+
+            let status = Command::new("git")
+                .arg("clone")
+                .arg(repo)
+                .arg(self.source_path())
+                .spawn()?
+                .wait()?;
+
+            if !status.success() {
+                Err(format_err!("Failed to clone {}", self.name))?
             }
+
+            // The service's devbox config only exists now that it's cloned.
+            self.rehydrate_from_devbox_toml()?;
+            self.run_lifecycle_hooks("after-clone")
         }
     }
 
@@ -178,73 +217,147 @@ impl Service {
         }
     }
 
+    /// Builds the error for a missing task, suggesting the closest task name.
+    pub fn task_not_found(&mut self, name: &str) -> TaskNotFound {
+        let tasks = self.tasks().unwrap_or_default();
+        let suggestion = task::did_you_mean(name, tasks.iter().map(|task| task.name.as_str()));
+
+        TaskNotFound {
+            task: name.to_owned(),
+            service: self.name.clone(),
+            suggestion,
+        }
+    }
+
     pub fn list_tasks(&mut self) -> Result<()> {
+        self.tasks_table(false).printstd();
+
+        Ok(())
+    }
+
+    /// Prints the tasks numbered from 1, as a menu to pick a task from.
+    pub fn list_tasks_numbered(&mut self) -> Result<()> {
+        self.tasks_table(true).printstd();
+
+        Ok(())
+    }
+
+    fn tasks_table(&mut self, numbered: bool) -> Table {
         let mut table = Table::new();
 
         table.set_format(*format::consts::FORMAT_CLEAN);
-        table.add_row(row!["TASK", "DESCRIPTION"]);
+
+        if numbered {
+            table.add_row(row!["#", "TASK", "DESCRIPTION"]);
+        } else {
+            table.add_row(row!["TASK", "DESCRIPTION"]);
+        }
 
         if let Ok(ref tasks) = self.tasks() {
-            for task in tasks {
-                table.add_row(row![task.name, task.description]);
+            for (index, task) in tasks.iter().enumerate() {
+                if numbered {
+                    table.add_row(row![index + 1, task.name, task.description]);
+                } else {
+                    table.add_row(row![task.name, task.description]);
+                }
             }
         }
 
-        table.printstd();
+        table
+    }
 
-        Ok(())
+    pub fn exec_tasks(&mut self, task_names: Vec<String>, keep_going: bool) -> Result<()> {
+        let tasks = self.resolve_tasks(&task_names)?;
+        let results = self.run_tasks(&tasks, keep_going, None)?;
+
+        task::print_summary(&results);
+        task::check_results(&results)
     }
 
-    pub fn exec_tasks(&mut self, task_names: Vec<String>) -> Result<()> {
+    /// Looks up every named task, failing before anything runs if one of them
+    /// is not defined for this service.
+    pub fn resolve_tasks(&mut self, task_names: &[String]) -> Result<Vec<Task>> {
+        let mut tasks = Vec::new();
+
         for name in task_names {
-            if let Some(ref task) = self.find_task(&name) {
-                self.exec_task(task)?;
-            } else {
-                eprintln!(
-                    "Task '{}' could not be found for service {}",
-                    name, self.name
-                );
+            match self.find_task(name) {
+                Some(task) => tasks.push(task),
+                None => Err(self.task_not_found(name))?,
             }
         }
 
-        Ok(())
+        Ok(tasks)
     }
 
-    fn exec_task(&self, task: &Task) -> Result<()> {
-        if self.devbox_compose_file().exists() {
-            let _ = cmd("docker-compose", self)
-                .arg("exec")
-                .arg(&self.name)
-                .args(&task.exec)
-                .spawn()?
-                .wait();
+    /// Runs `tasks` in order, skipping the remaining tasks after the first
+    /// failure unless `keep_going` is set. When a `prefix` is given the task
+    /// output is written line by line behind it instead of to the terminal.
+    pub fn run_tasks(
+        &self,
+        tasks: &[Task],
+        keep_going: bool,
+        prefix: Option<&Prefix>,
+    ) -> Result<Vec<TaskResult>> {
+        let mut results: Vec<TaskResult> = Vec::new();
+
+        for task in tasks {
+            if !keep_going && results.iter().any(|result| result.failed()) {
+                results.push(TaskResult::skipped(self, task));
+                continue;
+            }
 
-            return Ok(());
+            let started = Instant::now();
+            let status = self.spawn_task(task, TaskMode::Exec, prefix)?;
+            results.push(TaskResult::new(self, task, started.elapsed(), status));
         }
 
-        Err(format_err!(
-            "Failed to execute {} - missing docker-compose file",
-            self.name
-        ))
+        Ok(results)
     }
 
-    fn run_task(&self, task: &Task) -> Result<()> {
-        if self.devbox_compose_file().exists() {
-            let _ = cmd("docker-compose", self)
-                .arg("run")
-                .arg("--rm")
-                .arg(&self.name)
-                .args(&task.exec)
-                .spawn()?
-                .wait();
+    /// Spawns `task` and waits for it, running it in `default_mode` unless the
+    /// task sets its own mode.
+    pub fn spawn_task(
+        &self,
+        task: &Task,
+        default_mode: TaskMode,
+        prefix: Option<&Prefix>,
+    ) -> Result<ExitStatus> {
+        let mut cmd = match task.mode.unwrap_or(default_mode) {
+            TaskMode::Host => task.host_command(&self.source_path(), &self.env, &self.secrets)?,
+            mode => {
+                if !self.devbox_compose_file().exists() {
+                    Err(format_err!(
+                        "Failed to execute {} - missing docker-compose file",
+                        self.name
+                    ))?
+                }
 
-            return Ok(());
-        }
+                let mut cmd = cmd("docker-compose", self)?;
 
-        Err(format_err!(
-            "Failed to execute {} - missing docker-compose file",
-            self.name
-        ))
+                match mode {
+                    TaskMode::Run => cmd.arg("run").arg("--rm"),
+                    _ => cmd.arg("exec"),
+                };
+
+                if prefix.is_some() {
+                    cmd.arg("-T");
+                }
+
+                // Values are passed through docker-compose's environment so
+                // they don't show up in the process list.
+                for (key, value) in self.secrets.resolve(&task.environment(&self.env))? {
+                    cmd.arg("-e").arg(&key).env(key, value);
+                }
+
+                cmd.arg(&self.name).args(&task.exec);
+                cmd
+            }
+        };
+
+        match prefix {
+            Some(prefix) => output::spawn_prefixed(&mut cmd, prefix),
+            None => Ok(cmd.spawn()?.wait()?),
+        }
     }
 
     pub fn tasks(&mut self) -> Result<Vec<Task>> {
@@ -254,36 +367,60 @@ impl Service {
         }
     }
 
-    fn hooks(&mut self) -> Result<BTreeMap<String, Vec<Task>>> {
-        match self.hooks {
-            Some(ref v) => Ok(v.clone()),
-            None => Ok(BTreeMap::new()),
+    /// Resolves the tasks attached to `lifecycle`.
+    pub fn hook(&mut self, lifecycle: &str) -> Result<Option<Hook<Task>>> {
+        let config = match self.hooks.as_ref().and_then(|hooks| hooks.get(lifecycle)) {
+            Some(config) => config.clone(),
+            None => return Ok(None),
+        };
+
+        let mut tasks = Vec::new();
+
+        for entry in config.tasks() {
+            match *entry {
+                HookTask::Inline(ref inline) => tasks.push(inline.to_task()),
+                ref entry => {
+                    let name = entry.task_name().unwrap_or_default();
+                    match self.find_task(name) {
+                        Some(task) => tasks.push(task),
+                        None => Err(self.task_not_found(name))?,
+                    }
+                }
+            }
         }
+
+        let on_failure = config.on_failure();
+
+        Ok(Some(Hook { tasks, on_failure }))
     }
 
-    fn run_lifecycle_hooks(&mut self, lifecycle: &str) -> Result<()> {
+    /// Runs the tasks attached to `lifecycle`, applying the hook's failure
+    /// policy to any task that errors or exits with a non-zero status.
+    pub fn run_lifecycle_hooks(&mut self, lifecycle: &str) -> Result<()> {
+        let hook = match self.hook(lifecycle)? {
+            Some(hook) => hook,
+            None => return Ok(()),
+        };
+
         println!("{} Running {} hooks", "INFO".green(), lifecycle);
-        let tasks = self.tasks_for_hook(lifecycle);
-        if !tasks.is_empty() {
-            for task in tasks {
-                let _ = self.run_task(&task);
-            }
+
+        for task in &hook.tasks {
+            let outcome = self.spawn_task(task, TaskMode::Run, None);
+            hook::check_task(&self.name, lifecycle, hook.on_failure, task, outcome)?;
         }
 
         Ok(())
     }
 
-    fn tasks_for_hook(&mut self, name: &str) -> Vec<Task> {
-        self.hooks()
-            .unwrap_or_default()
-            .get(name)
-            .unwrap_or(&vec![])
-            .to_vec()
-    }
-
     fn insert_tasks(&mut self, values: &toml::Value) -> Result<()> {
         match values.get("tasks") {
-            Some(v) => self.tasks = v.clone().try_into::<Vec<Task>>().ok(),
+            Some(v) => {
+                let tasks = v
+                    .clone()
+                    .try_into::<Vec<Task>>()
+                    .with_context(|_| format!("Invalid tasks for service {}", self.name))?;
+                self.tasks = Some(tasks);
+            }
             None => println!("No tasks found for service '{}'", self.name),
         };
 
@@ -291,32 +428,41 @@ impl Service {
     }
 
     fn insert_hooks(&mut self, values: &toml::Value) -> Result<()> {
-        let hooks = match values.get("hooks") {
-            Some(v) => {
-                let mut hooks = BTreeMap::new();
-                let map = v.clone().try_into::<BTreeMap<String, Vec<String>>>()?;
-
-                for (key, task_names) in &map {
-                    let mut tasks: Vec<Task> = Vec::new();
-                    for name in task_names {
-                        match self.find_task(name.as_str()) {
-                            Some(task) => tasks.push(task),
-                            None => println!(
-                                "{} Task `{}` was not found in the available tasks",
-                                "WARN".yellow(),
-                                name.as_str()
-                            ),
-                        }
-                    }
-                    hooks.insert(key.clone(), tasks);
+        let hooks = hook::parse_hooks(values)
+            .with_context(|_| format!("Invalid hooks for service {}", self.name))?;
+
+        // A service's hooks always run in the service itself.
+        for (lifecycle, config) in &hooks {
+            for entry in config.tasks() {
+                if let HookTask::Inline(InlineTask {
+                    service: Some(ref service),
+                    ..
+                }) = *entry
+                {
+                    Err(format_err!(
+                        "The {} hook of service {} names service {}; only project hooks can run tasks in other services",
+                        lifecycle,
+                        self.name,
+                        service
+                    ))?
                 }
-
-                Some(hooks)
             }
-            None => None,
-        };
+        }
 
-        self.hooks = hooks;
+        self.hooks = Some(hooks);
+
+        Ok(())
+    }
+
+    fn insert_env(&mut self, values: &toml::Value) -> Result<()> {
+        let config = EnvConfig::parse(values)?;
+        let mut env = self.project_env.clone();
+
+        if let Some(dir) = self.devbox_toml_file().parent() {
+            config.apply(dir, &mut env)?;
+        }
+
+        self.env = env;
 
         Ok(())
     }
@@ -331,6 +477,31 @@ impl Service {
         }
     }
 
+    /// The project's compose file and generated overrides along with this
+    /// service's overlay, run from the service's `.devbox` directory.
+    pub fn compose_files(&self) -> ComposeFiles {
+        let devbox_compose_file = PathBuf::from(env::var("COMPOSE_FILE").unwrap());
+        let project_dir = devbox_compose_file.parent().unwrap().to_path_buf();
+        let service_compose_file = self.devbox_compose_file();
+        let ports_copy = ComposeFiles::port_overrides_copy(&project_dir, Some(&self.name));
+
+        ComposeFiles::layered(&self.base_compose_files, &devbox_compose_file)
+            .file(network::overrides_file(&project_dir))
+            .overlay(self)
+            .port_overrides(&project_dir, &ports_copy)
+            .project_directory(service_compose_file.parent().unwrap())
+    }
+
+    /// Checks that the host ports the service and the project publish are
+    /// free, moving the ones that aren't when `remap` is set.
+    pub fn check_ports(&self, remap: bool) -> Result<()> {
+        let devbox_compose_file = PathBuf::from(env::var("COMPOSE_FILE").unwrap());
+        let project_dir = devbox_compose_file.parent().unwrap();
+
+        ports::check_ports(&self.project_name, project_dir, &self.compose_files(), |_| true, remap)
+            .map(|_| ())
+    }
+
     pub fn devbox_compose_file(&self) -> PathBuf {
         match self.path {
             Some(ref path) => path.join(COMPOSE_PATH),
@@ -1,6 +1,254 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::time::Duration;
+
+use colored::*;
+use prettytable::format;
+use prettytable::Table;
+
+use errors::*;
+use secrets::Secrets;
+use service::Service;
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Task {
     pub name: String,
     pub description: String,
     pub exec: Vec<String>,
+    /// How to run the task. When unset, `tasks exec` runs it in the service's
+    /// running container and hooks run it in a new one.
+    pub mode: Option<TaskMode>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskMode {
+    /// `docker-compose exec` in the service's running container.
+    Exec,
+    /// `docker-compose run --rm` in a fresh container.
+    Run,
+    /// Directly on the host.
+    Host,
+}
+
+impl Task {
+    /// Builds the command of a host mode task, run from `dir` with the task's
+    /// env layered over `env` and its secrets looked up.
+    pub fn host_command(
+        &self,
+        dir: &Path,
+        env: &BTreeMap<String, String>,
+        secrets: &Secrets,
+    ) -> Result<Command> {
+        let (program, args) = self.exec
+            .split_first()
+            .ok_or_else(|| format_err!("Task '{}' has nothing to exec", self.name))?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(args)
+            .current_dir(dir)
+            .envs(secrets.resolve(&self.environment(env))?);
+
+        Ok(cmd)
+    }
+
+    /// `env` with the task's own env layered over it.
+    pub fn environment(&self, env: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        let mut env = env.clone();
+        env.extend(self.env.clone());
+        env
+    }
+}
+
+/// The outcome of a single task run by `Service::exec_tasks`.
+#[derive(Clone, Debug)]
+pub struct TaskResult {
+    pub service: String,
+    pub name: String,
+    pub duration: Duration,
+    /// `None` when the task was skipped because an earlier task failed.
+    pub status: Option<ExitStatus>,
+    /// Why the task was skipped, when it wasn't for an earlier failure.
+    pub reason: Option<String>,
+}
+
+impl TaskResult {
+    pub fn new(service: &Service, task: &Task, duration: Duration, status: ExitStatus) -> Self {
+        TaskResult {
+            service: service.name.clone(),
+            name: task.name.clone(),
+            duration,
+            status: Some(status),
+            reason: None,
+        }
+    }
+
+    pub fn skipped(service: &Service, task: &Task) -> Self {
+        TaskResult {
+            service: service.name.clone(),
+            name: task.name.clone(),
+            duration: Duration::from_secs(0),
+            status: None,
+            reason: None,
+        }
+    }
+
+    /// A task that was never attempted for a service, such as one the
+    /// service doesn't define.
+    pub fn not_run(service: &str, task: &str, reason: &str) -> Self {
+        TaskResult {
+            service: service.to_owned(),
+            name: task.to_owned(),
+            duration: Duration::from_secs(0),
+            status: None,
+            reason: Some(reason.to_owned()),
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        match self.status {
+            Some(status) => !status.success(),
+            None => false,
+        }
+    }
+
+    /// The exit code of the task, using `1` for processes killed by a signal.
+    pub fn code(&self) -> Option<i32> {
+        self.status.map(|status| status.code().unwrap_or(1))
+    }
+}
+
+pub fn print_summary(results: &[TaskResult]) {
+    let mut table = Table::new();
+
+    table.set_format(*format::consts::FORMAT_CLEAN);
+    table.add_row(row!["SERVICE", "TASK", "DURATION", "STATUS"]);
+
+    for result in results {
+        let status = match result.status {
+            Some(status) if status.success() => "ok".green(),
+            Some(status) => match status.code() {
+                Some(code) => format!("exit {}", code).red(),
+                None => "killed".red(),
+            },
+            None => match result.reason {
+                Some(ref reason) => format!("skipped: {}", reason).yellow(),
+                None => "skipped".yellow(),
+            },
+        };
+
+        table.add_row(row![
+            result.service,
+            result.name,
+            format_duration(result.duration),
+            status.to_string()
+        ]);
+    }
+
+    let failed = results.iter().filter(|result| result.failed()).count();
+    let skipped = results.iter().filter(|result| result.status.is_none()).count();
+    let passed = results.len() - failed - skipped;
+
+    println!();
+    table.printstd();
+    println!(
+        "\n{} passed, {} failed, {} skipped",
+        passed.to_string().green(),
+        failed.to_string().red(),
+        skipped.to_string().yellow()
+    );
+}
+
+/// Turns the first failed task into a `TaskFailed` error.
+pub fn check_results(results: &[TaskResult]) -> Result<()> {
+    match results.iter().find(|result| result.failed()) {
+        Some(result) => Err(TaskFailed {
+            service: result.service.clone(),
+            task: result.name.clone(),
+            code: result.code().unwrap_or(1),
+        })?,
+        None => Ok(()),
+    }
+}
+
+/// Finds the candidate closest to `name` by edit distance, as long as it is
+/// close enough to plausibly be a typo.
+pub fn did_you_mean<'a, I>(name: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let threshold = ::std::cmp::max(2, name.chars().count() / 3);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= threshold)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate.to_owned())
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            let insertion = current[j] + 1;
+            let deletion = previous[j + 1] + 1;
+
+            current.push(substitution.min(insertion).min(deletion));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}.{:02}s", secs, duration.subsec_nanos() / 10_000_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("migrate", "migrate"), 0);
+        assert_eq!(edit_distance("migrat", "migrate"), 1);
+        assert_eq!(edit_distance("mirgate", "migrate"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn did_you_mean_suggests_the_closest_task() {
+        let tasks = vec!["migrate", "test", "console"];
+
+        assert_eq!(did_you_mean("migrat", tasks.clone()), Some("migrate".to_owned()));
+        assert_eq!(did_you_mean("tset", tasks.clone()), Some("test".to_owned()));
+    }
+
+    #[test]
+    fn did_you_mean_gives_up_on_distant_names() {
+        let tasks = vec!["migrate", "test", "console"];
+
+        assert_eq!(did_you_mean("deploy", tasks.clone()), None);
+        assert_eq!(did_you_mean("anything", Vec::new()), None);
+    }
 }
@@ -0,0 +1,469 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use prettytable::format;
+use prettytable::Table;
+
+use errors::*;
+use project::devbox_dir;
+
+/// A template `devbox new` can create a project from: a set of files
+/// written to the project directory, with `{{project}}` and `{{network}}`
+/// replaced by the project's name and the name of its network.
+#[derive(Clone, Debug)]
+pub enum Template {
+    Builtin(&'static Builtin),
+    /// A directory under `~/.config/devbox/templates`.
+    Dir { name: String, path: PathBuf },
+}
+
+/// A template that ships with devbox.
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub description: &'static str,
+    files: &'static [(&'static str, &'static str)],
+}
+
+pub const DEFAULT_TEMPLATE: &str = "default";
+
+/// The directory under `~/.config/devbox` holding user templates, which is
+/// why no project can be named after it.
+pub const TEMPLATES_DIR: &str = "templates";
+
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: DEFAULT_TEMPLATE,
+        description: "A commented example with Redis, MySQL, and Postgres",
+        files: &[("config.toml", DEFAULT_TOML), ("docker-compose.yml", DEFAULT_COMPOSE_YAML)],
+    },
+    Builtin {
+        name: "empty",
+        description: "No services at all",
+        files: &[("config.toml", EMPTY_TOML), ("docker-compose.yml", EMPTY_COMPOSE_YAML)],
+    },
+    Builtin {
+        name: "postgres-redis",
+        description: "Postgres and Redis",
+        files: &[
+            ("config.toml", POSTGRES_REDIS_TOML),
+            ("docker-compose.yml", POSTGRES_REDIS_COMPOSE_YAML),
+        ],
+    },
+    Builtin {
+        name: "kafka-stack",
+        description: "Kafka and ZooKeeper",
+        files: &[
+            ("config.toml", KAFKA_STACK_TOML),
+            ("docker-compose.yml", KAFKA_STACK_COMPOSE_YAML),
+        ],
+    },
+];
+
+impl Template {
+    pub fn name(&self) -> &str {
+        match *self {
+            Template::Builtin(builtin) => builtin.name,
+            Template::Dir { ref name, .. } => name,
+        }
+    }
+
+    /// The template's files, as paths relative to the project directory with
+    /// their placeholders filled in.
+    pub fn render(&self, project: &str, network: &str) -> Result<Vec<(PathBuf, String)>> {
+        let files = match *self {
+            Template::Builtin(builtin) => builtin
+                .files
+                .iter()
+                .map(|&(path, contents)| (PathBuf::from(path), contents.to_owned()))
+                .collect(),
+            Template::Dir { ref path, .. } => read_dir(path, path)?,
+        };
+
+        Ok(files
+            .into_iter()
+            .map(|(path, contents)| {
+                let contents = contents
+                    .replace("{{project}}", project)
+                    .replace("{{network}}", network);
+                (path, contents)
+            })
+            .collect())
+    }
+}
+
+/// The built-in templates along with those in `~/.config/devbox/templates`.
+#[derive(Clone, Debug)]
+pub struct Templates {
+    dir: PathBuf,
+}
+
+impl Templates {
+    pub fn new() -> Result<Self> {
+        Ok(Templates {
+            dir: devbox_dir(TEMPLATES_DIR)?,
+        })
+    }
+
+    /// Every template, built-ins first.
+    pub fn list(&self) -> Result<Vec<Template>> {
+        let mut templates: Vec<Template> = BUILTINS.iter().map(Template::Builtin).collect();
+
+        if self.dir.exists() {
+            let mut dirs = Vec::new();
+
+            for entry in fs::read_dir(&self.dir)? {
+                let path = entry?.path();
+
+                if path.is_dir() {
+                    if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                        dirs.push(Template::Dir {
+                            name: name.to_owned(),
+                            path: path.clone(),
+                        });
+                    }
+                }
+            }
+
+            dirs.sort_by(|a, b| a.name().cmp(b.name()));
+            templates.extend(dirs);
+        }
+
+        Ok(templates)
+    }
+
+    pub fn find(&self, name: &str) -> Result<Template> {
+        self.list()?
+            .into_iter()
+            .find(|template| template.name() == name)
+            .ok_or_else(|| TemplateNotFound(name.to_owned()).into())
+    }
+
+    /// Copies a directory, or clones a git repository, into a new template.
+    /// The template needs a `config.toml` and a `docker-compose.yml`.
+    pub fn add(&self, name: &str, source: &str) -> Result<PathBuf> {
+        check_name(name)?;
+
+        if self.find(name).is_ok() {
+            Err(format_err!("Template {} already exists", name))?
+        }
+
+        let target = self.dir.join(name);
+        fs::create_dir_all(&self.dir)?;
+
+        if Path::new(source).is_dir() {
+            copy_dir(Path::new(source), &target)?;
+        } else {
+            let status = Command::new("git")
+                .args(["clone", "--depth", "1", source])
+                .arg(&target)
+                .status()?;
+
+            if !status.success() {
+                Err(format_err!("Unable to clone {}", source))?
+            }
+
+            fs::remove_dir_all(target.join(".git"))?;
+        }
+
+        for file in &["config.toml", "docker-compose.yml"] {
+            if !target.join(file).exists() {
+                fs::remove_dir_all(&target)?;
+                Err(format_err!("{} has no {}, so it can't be a template", source, file))?
+            }
+        }
+
+        Ok(target)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        match self.find(name)? {
+            Template::Builtin(_) => Err(format_err!("{} is a built-in template", name))?,
+            Template::Dir { path, .. } => fs::remove_dir_all(path)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn print_list(&self) -> Result<()> {
+        let mut table = Table::new();
+
+        table.set_format(*format::consts::FORMAT_CLEAN);
+        table.add_row(row!["TEMPLATE", "SOURCE"]);
+
+        for template in self.list()? {
+            match template {
+                Template::Builtin(builtin) => {
+                    table.add_row(row![builtin.name, format!("built-in: {}", builtin.description)])
+                }
+                Template::Dir { name, path } => table.add_row(row![name, path.display()]),
+            };
+        }
+
+        table.printstd();
+
+        Ok(())
+    }
+}
+
+/// Template names become directory names.
+fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format_err!(
+            "Invalid template name '{}'; use letters, digits, '-', '_', and '.'",
+            name
+        ))
+    }
+}
+
+/// The text files under `dir`, relative to `root`.
+fn read_dir(root: &Path, dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            files.extend(read_dir(root, &path)?);
+        } else {
+            let contents = fs::read_to_string(&path)
+                .map_err(|err| format_err!("Unable to read {}: {}", path.display(), err))?;
+            let relative = path.strip_prefix(root)?.to_path_buf();
+            files.push((relative, contents));
+        }
+    }
+
+    Ok(files)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.file_name() == ".git" {
+            continue;
+        }
+
+        if path.is_dir() {
+            copy_dir(&path, &to.join(entry.file_name()))?;
+        } else {
+            fs::copy(&path, to.join(entry.file_name()))?;
+        }
+    }
+
+    Ok(())
+}
+
+const DEFAULT_TOML: &str = r#"# Example devbox project configuration
+#
+# Devbox can automatically create external docker volumes by specifying each
+# volume as a name.
+#
+# volumes = [
+#   "mysql",
+#   "postgres"
+# ]
+#
+# Service definitions allow devbox to know how to clone or find the source code.
+#
+# * `name` - The name of the service
+# * `git` - The source of a git repository
+# * `path` - The path to the source on disk (optional)
+#
+# [services]
+# service1 = { git = "https://github.com/scrogson/service1" }
+# service2 = { path = "/path/to/service2" }
+#
+# Personal changes, such as a service's `path` or `disabled = true`, go in a
+# `config.local.toml` next to this file.
+#
+# Devbox creates the docker networks used by the project. Without a `networks`
+# table it creates a single network named after the project.
+#
+# * `driver` - The network driver (optional)
+# * `subnet` - The subnet in CIDR format (optional)
+# * `aliases` - Extra names for services on the network (optional)
+#
+# [networks.example]
+# subnet = "172.28.0.0/16"
+# aliases = { postgres = ["db"] }
+#
+# Profiles name a subset of the services and compose services, selected with
+# `--profile` or `DEVBOX_PROFILE`.
+#
+# [profiles.minimal]
+# services = ["service1", "postgres"]
+"#;
+
+const DEFAULT_COMPOSE_YAML: &str = r#"# This is an example docker-compose config file
+#
+# Replace the contents based on your project's requirements.
+#
+# See https://docs.docker.com/compose/compose-file for details.
+version: "3"
+
+networks:
+  {{network}}:
+    external: true
+
+volumes:
+  postgres:
+    external: true
+  mysql:
+    external: true
+
+services:
+
+  redis:
+    image: redis
+    restart: "on-failure"
+    ports:
+      - "127.0.0.1:6379:6379"
+    networks:
+      - {{network}}
+
+  mysql:
+    image: mysql:5.6
+    restart: "on-failure"
+    environment:
+      MYSQL_ALLOW_EMPTY_PASSWORD: "yes"
+    volumes:
+      - mysql:/var/lib/mysql
+    ports:
+      - "127.0.0.1:3306:3306"
+    networks:
+      - {{network}}
+
+  postgres:
+    image: postgres:9.6
+    restart: "on-failure"
+    environment:
+      POSTGRES_USER: "postgres"
+      POSTGRES_PASSWORD: "postgres"
+      PGDATA: /var/lib/postgresql/data/pgdata
+    volumes:
+      - postgres:/var/lib/postgresql/data/pgdata
+    ports:
+      - "127.0.0.1:5432:5432"
+    networks:
+      - {{network}}
+"#;
+
+const EMPTY_TOML: &str = r#"# devbox project configuration for {{project}}
+#
+# Add services with `devbox config -p {{project}} add-service <name> --git <url>`.
+"#;
+
+const EMPTY_COMPOSE_YAML: &str = r#"version: "3"
+
+networks:
+  {{network}}:
+    external: true
+
+services: {}
+"#;
+
+const POSTGRES_REDIS_TOML: &str = r#"# devbox project configuration for {{project}}
+volumes = ["{{project}}-postgres"]
+
+[services]
+"#;
+
+const POSTGRES_REDIS_COMPOSE_YAML: &str = r#"version: "3"
+
+networks:
+  {{network}}:
+    external: true
+
+volumes:
+  {{project}}-postgres:
+    external: true
+
+services:
+
+  postgres:
+    image: postgres:16
+    restart: "on-failure"
+    environment:
+      POSTGRES_USER: "postgres"
+      POSTGRES_PASSWORD: "postgres"
+    volumes:
+      - {{project}}-postgres:/var/lib/postgresql/data
+    ports:
+      - "127.0.0.1:5432:5432"
+    healthcheck:
+      test: ["CMD", "pg_isready", "-U", "postgres"]
+      interval: 5s
+    networks:
+      - {{network}}
+
+  redis:
+    image: redis:7
+    restart: "on-failure"
+    ports:
+      - "127.0.0.1:6379:6379"
+    networks:
+      - {{network}}
+"#;
+
+const KAFKA_STACK_TOML: &str = r#"# devbox project configuration for {{project}}
+volumes = ["{{project}}-kafka", "{{project}}-zookeeper"]
+
+[services]
+"#;
+
+const KAFKA_STACK_COMPOSE_YAML: &str = r#"version: "3"
+
+networks:
+  {{network}}:
+    external: true
+
+volumes:
+  {{project}}-kafka:
+    external: true
+  {{project}}-zookeeper:
+    external: true
+
+services:
+
+  zookeeper:
+    image: confluentinc/cp-zookeeper:7.6.0
+    restart: "on-failure"
+    environment:
+      ZOOKEEPER_CLIENT_PORT: 2181
+    volumes:
+      - {{project}}-zookeeper:/var/lib/zookeeper/data
+    networks:
+      - {{network}}
+
+  kafka:
+    image: confluentinc/cp-kafka:7.6.0
+    restart: "on-failure"
+    depends_on:
+      - zookeeper
+    environment:
+      KAFKA_BROKER_ID: 1
+      KAFKA_ZOOKEEPER_CONNECT: zookeeper:2181
+      KAFKA_ADVERTISED_LISTENERS: PLAINTEXT://kafka:29092,PLAINTEXT_HOST://localhost:9092
+      KAFKA_LISTENER_SECURITY_PROTOCOL_MAP: PLAINTEXT:PLAINTEXT,PLAINTEXT_HOST:PLAINTEXT
+      KAFKA_INTER_BROKER_LISTENER_NAME: PLAINTEXT
+      KAFKA_OFFSETS_TOPIC_REPLICATION_FACTOR: 1
+    volumes:
+      - {{project}}-kafka:/var/lib/kafka/data
+    ports:
+      - "127.0.0.1:9092:9092"
+    networks:
+      - {{network}}
+"#;
@@ -0,0 +1,303 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::{DateTime, Local};
+use colored::*;
+use prettytable::format;
+use prettytable::Table;
+
+use docker::{self, format_size, volume_size, HELPER_IMAGE};
+use errors::*;
+use project::{self, Project};
+
+/// The directory inside a volume a snapshot is extracted to before it
+/// replaces the volume's contents.
+const RESTORE_STAGING_DIR: &str = ".devbox-restore";
+
+/// A tarball of a volume's contents.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub volume: String,
+    pub tag: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub created: DateTime<Local>,
+}
+
+/// The `volumes` of a project and their snapshots, kept in
+/// `~/.config/devbox/<project>/snapshots/<volume>/<tag>.tar.gz`.
+#[derive(Clone, Debug)]
+pub struct Volumes {
+    names: Vec<String>,
+    dir: PathBuf,
+}
+
+impl Volumes {
+    pub fn new(project: &Project) -> Result<Self> {
+        Ok(Volumes {
+            names: project.volumes.clone(),
+            dir: project::devbox_dir(&project.name)?.join("snapshots"),
+        })
+    }
+
+    /// Writes the contents of `volume` to a snapshot, tagged with the current
+    /// time unless a tag is given. An existing snapshot with the same tag is
+    /// replaced.
+    pub fn snapshot(&self, volume: &str, tag: Option<&str>) -> Result<Snapshot> {
+        self.check_volume(volume)?;
+
+        if !docker::volume_exists(volume)? {
+            Err(format_err!("Volume {} has not been created yet", volume))?
+        }
+
+        let tag = match tag {
+            Some(tag) => check_tag(tag)?.to_owned(),
+            None => Local::now().format("%Y%m%d-%H%M%S").to_string(),
+        };
+
+        if !docker::containers_using(volume)?.is_empty() {
+            println!(
+                "{} Volume {} is in use; the snapshot may not be consistent",
+                "WARN".yellow(),
+                volume
+            );
+        }
+
+        let dir = self.dir.join(volume);
+        fs::create_dir_all(&dir)?;
+
+        let archive = format!("/snapshots/{}.tar.gz", tag);
+        docker::run(
+            Command::new("docker")
+                .args(["run", "--rm"])
+                .arg("-v")
+                .arg(format!("{}:/volume:ro", volume))
+                .arg("-v")
+                .arg(mount(&dir, "/snapshots"))
+                .args([HELPER_IMAGE, "tar", "czf", &archive, "-C", "/volume", "."]),
+            &format!("snapshot volume {}", volume),
+        )?;
+
+        self.find_snapshot(volume, Some(&tag))
+    }
+
+    /// Replaces the contents of `volume` with a snapshot, the latest one unless
+    /// a tag is given. Refuses to touch a volume that running containers use.
+    pub fn restore(&self, volume: &str, tag: Option<&str>) -> Result<Snapshot> {
+        self.check_volume(volume)?;
+
+        let snapshot = self.find_snapshot(volume, tag)?;
+        let containers = docker::containers_using(volume)?;
+
+        if !containers.is_empty() {
+            Err(format_err!(
+                "Volume {} is in use by {} running container(s); stop them before restoring",
+                volume,
+                containers.len()
+            ))?
+        }
+
+        // Extract next to the current contents first, so a bad archive
+        // leaves the volume as it was, then swap them.
+        let script = format!(
+            "set -e
+             rm -rf /volume/{staging}
+             mkdir /volume/{staging}
+             tar xzpf /snapshots/{tag}.tar.gz -C /volume/{staging} || {{ rm -rf /volume/{staging}; exit 1; }}
+             find /volume -mindepth 1 -maxdepth 1 ! -name {staging} -exec rm -rf {{}} +
+             find /volume/{staging} -mindepth 1 -maxdepth 1 -exec mv {{}} /volume/ \\;
+             rmdir /volume/{staging}",
+            staging = RESTORE_STAGING_DIR,
+            tag = snapshot.tag
+        );
+        docker::run(
+            Command::new("docker")
+                .args(["run", "--rm"])
+                .arg("-v")
+                .arg(format!("{}:/volume", volume))
+                .arg("-v")
+                .arg(mount(&self.dir.join(volume), "/snapshots:ro"))
+                .args([HELPER_IMAGE, "sh", "-c", &script]),
+            &format!("restore volume {}", volume),
+        )?;
+
+        Ok(snapshot)
+    }
+
+    /// Copies the contents of `source` into `target`, a volume that must not
+    /// exist yet. The target is removed again if the copy fails.
+    pub fn clone_volume(&self, source: &str, target: &str) -> Result<()> {
+        self.check_volume(source)?;
+
+        if !docker::volume_exists(source)? {
+            Err(format_err!("Volume {} has not been created yet", source))?
+        }
+
+        if docker::volume_exists(target)? {
+            Err(format_err!("Volume {} already exists", target))?
+        }
+
+        docker::run(
+            Command::new("docker").args(["volume", "create", "--name", target]),
+            &format!("create volume {}", target),
+        )?;
+
+        let copied = docker::run(
+            Command::new("docker")
+                .args(["run", "--rm"])
+                .arg("-v")
+                .arg(format!("{}:/from:ro", source))
+                .arg("-v")
+                .arg(format!("{}:/to", target))
+                .args([HELPER_IMAGE, "cp", "-a", "/from/.", "/to/"]),
+            &format!("copy volume {} to {}", source, target),
+        );
+
+        if copied.is_err() {
+            let _ = docker::run(
+                Command::new("docker").args(["volume", "rm", target]),
+                &format!("remove volume {}", target),
+            );
+        }
+
+        copied
+    }
+
+    /// The snapshots of a volume, oldest first.
+    pub fn snapshots(&self, volume: &str) -> Result<Vec<Snapshot>> {
+        let dir = self.dir.join(volume);
+
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut snapshots = Vec::new();
+
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let tag = match path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".tar.gz"))
+            {
+                Some(tag) => tag.to_owned(),
+                None => continue,
+            };
+            let metadata = fs::metadata(&path)?;
+
+            snapshots.push(Snapshot {
+                volume: volume.to_owned(),
+                tag,
+                size: metadata.len(),
+                created: DateTime::from(metadata.modified()?),
+                path,
+            });
+        }
+
+        snapshots.sort_by(|a, b| a.created.cmp(&b.created).then(a.tag.cmp(&b.tag)));
+
+        Ok(snapshots)
+    }
+
+    /// Prints every project volume with its size and latest snapshot.
+    pub fn print_list(&self) -> Result<()> {
+        let mut table = Table::new();
+
+        table.set_format(*format::consts::FORMAT_CLEAN);
+        table.add_row(row!["VOLUME", "SIZE", "SNAPSHOTS", "LAST SNAPSHOT"]);
+
+        for name in &self.names {
+            let size = match volume_size(name)? {
+                Some(size) => format_size(size),
+                None => "not created".into(),
+            };
+            let snapshots = self.snapshots(name)?;
+            let last = match snapshots.last() {
+                Some(snapshot) => format!(
+                    "{} ({})",
+                    snapshot.tag,
+                    snapshot.created.format("%Y-%m-%d %H:%M")
+                ),
+                None => "-".into(),
+            };
+
+            table.add_row(row![name, size, snapshots.len(), last]);
+        }
+
+        table.printstd();
+
+        Ok(())
+    }
+
+    /// Prints the snapshots of a single volume.
+    pub fn print_snapshots(&self, volume: &str) -> Result<()> {
+        self.check_volume(volume)?;
+
+        let mut table = Table::new();
+
+        table.set_format(*format::consts::FORMAT_CLEAN);
+        table.add_row(row!["TAG", "SIZE", "CREATED"]);
+
+        for snapshot in self.snapshots(volume)? {
+            table.add_row(row![
+                snapshot.tag,
+                format_size(snapshot.size),
+                snapshot.created.format("%Y-%m-%d %H:%M:%S")
+            ]);
+        }
+
+        table.printstd();
+
+        Ok(())
+    }
+
+    fn find_snapshot(&self, volume: &str, tag: Option<&str>) -> Result<Snapshot> {
+        let mut snapshots = self.snapshots(volume)?;
+
+        let snapshot = match tag {
+            Some(tag) => snapshots
+                .into_iter()
+                .find(|snapshot| snapshot.tag == tag),
+            None => snapshots.pop(),
+        };
+
+        snapshot.ok_or_else(|| {
+            SnapshotNotFound {
+                volume: volume.to_owned(),
+                tag: tag.map(String::from),
+            }
+            .into()
+        })
+    }
+
+    fn check_volume(&self, volume: &str) -> Result<()> {
+        if self.names.iter().any(|name| name == volume) {
+            Ok(())
+        } else {
+            Err(VolumeNotFound(volume.to_owned()))?
+        }
+    }
+}
+
+/// Tags become file names, so keep them to a safe set of characters.
+fn check_tag(tag: &str) -> Result<&str> {
+    let valid = !tag.is_empty()
+        && !tag.starts_with('.')
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+
+    if valid {
+        Ok(tag)
+    } else {
+        Err(format_err!(
+            "Invalid snapshot tag '{}'; use letters, digits, '-', '_', and '.'",
+            tag
+        ))
+    }
+}
+
+fn mount(dir: &Path, target: &str) -> String {
+    format!("{}:{}", dir.display(), target)
+}